@@ -0,0 +1,467 @@
+extern crate colored;
+
+use self::colored::*;
+use bft::io::Issue;
+use bft::runtime;
+use bft::runtime::{debug, Abort};
+use bft::source::Token;
+use options::Options;
+use std::cmp;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+
+const TAPE_RADIUS: isize = 8; // cells shown on each side of the pointer
+
+const HELP: &str = "\
+Commands:
+  s, step [COUNT]  run the next op, or the next COUNT ops
+  n, next          run the next op, and all of the loop if it starts one
+  o, out           run until the loop the next op is in has ended
+  c, continue      run until the code ends or stops
+  u, until LINE    run until the next op is on LINE
+  r, reverse [COUNT]     undo the last op, or the last COUNT ops
+  rc, reverse-continue   undo ops until a breakpoint or watchpoint would have stopped the code
+  b, break L:C     stop before running the op at line L and column C
+  w, watch CELL    stop after an op changes CELL
+  i, if CONDITION  stop after an op makes CONDITION true, such as: if ptr > 3 and cell[ptr] == 10
+                   conditions can use numbers, ptr, instrs (the number of ops run), cell[INDEX],
+                   + - == != < <= > >= and or not and parentheses
+  d, delete L:C    remove the breakpoint at line L and column C
+  d, delete CELL   stop watching CELL
+  d, delete CONDITION  remove a condition
+  save FILE        write the state of the code to FILE, to load later or attach to a bug report
+  load FILE        carry on from a state written by save
+  l, line          show the next op and the tape
+  h, help          show this help
+  q, quit          stop debugging
+An empty line repeats the last command.";
+
+// A line-oriented debugger that runs code on a debug::Runtime one op at a time
+pub struct Debugger<'a, D> {
+    options: &'a Options,
+    runtime: debug::Runtime<D>,
+    line_ended: bool, // if the output of the code so far ends with a newline
+}
+
+impl<'a, D: runtime::Cell> Debugger<'a, D> {
+    pub fn new(options: &'a Options, tokens: &[Token]) -> Debugger<'a, D> {
+        let mut runtime = debug::Runtime::new();
+        runtime.set_config(options.runtime_config());
+        runtime.add_tokens(tokens);
+        runtime.set_history_limit(options.history_size);
+        runtime.set_loop_detection(options.detect_loops);
+        Debugger {
+            options,
+            runtime,
+            line_ended: true,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let _ = self.run_with(&mut stdin.lock(), &mut stdout.lock());
+    }
+
+    // reads commands from input until it ends or one quits, with everything shown and the output
+    // of the code written to output
+    fn run_with<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> io::Result<()> {
+        writeln!(output, "Type h for help")?;
+        self.show_position(output)?;
+        let mut last_command = String::new();
+        loop {
+            write!(output, "{} ", "(bft)".bold())?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if !line.trim().is_empty() {
+                last_command = line.trim().to_string();
+            }
+            if !self.command(&last_command, input, output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // runs one command, returning false if it was to quit
+    fn command<R: BufRead, W: Write>(
+        &mut self,
+        line: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> io::Result<bool> {
+        let (command, argument) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], Some(line[i..].trim())),
+            None => (line, None),
+        };
+        let abort = match (command, argument) {
+            ("s", count) | ("step", count) => match count.unwrap_or("1").parse() {
+                Ok(count) => self.step(count, output)?,
+                Err(_) => usage("step [COUNT]", output)?,
+            },
+            ("n", None) | ("next", None) => {
+                let depth = self.runtime.loop_depth();
+                self.run_while(|runtime| runtime.loop_depth() > depth, output)?
+            }
+            ("o", None) | ("out", None) => match self.runtime.loop_depth() {
+                0 => {
+                    writeln!(output, "The next op is not in a loop")?;
+                    None
+                }
+                depth => self.run_while(|runtime| runtime.loop_depth() >= depth, output)?,
+            },
+            ("c", None) | ("continue", None) => Some(self.run_code(None, output)?),
+            ("r", count) | ("reverse", count) => match count.unwrap_or("1").parse() {
+                Ok(0) | Err(_) => usage("reverse [COUNT]", output)?,
+                Ok(count) => Some(self.runtime.run_back(Some(count))),
+            },
+            ("rc", None) | ("reverse-continue", None) => Some(self.runtime.run_back(None)),
+            ("u", Some(line)) | ("until", Some(line)) => match line.parse() {
+                Ok(line) => self.run_while(
+                    |runtime| runtime.next_span().map(|span| span.line()) != Some(line),
+                    output,
+                )?,
+                Err(_) => usage("until LINE", output)?,
+            },
+            ("b", Some(location)) | ("break", Some(location)) => {
+                match parse_location(location) {
+                    Some((line, col)) => self.runtime.add_breakpoint(line, col),
+                    None => writeln!(output, "Usage: break LINE:COL")?,
+                }
+                None
+            }
+            ("w", Some(cell)) | ("watch", Some(cell)) => {
+                match cell.parse() {
+                    Ok(cell) => self.runtime.add_watchpoint(cell),
+                    Err(_) => writeln!(output, "Usage: watch CELL")?,
+                }
+                None
+            }
+            ("i", Some(condition)) | ("if", Some(condition)) => {
+                match runtime::Condition::parse(condition) {
+                    Ok(condition) => self.runtime.add_condition(condition),
+                    Err(message) => writeln!(output, "{}", message)?,
+                }
+                None
+            }
+            ("d", Some(argument)) | ("delete", Some(argument)) => {
+                self.delete(argument, output)?;
+                None
+            }
+            ("save", Some(path)) => {
+                let runtime = &self.runtime;
+                let saved = fs::File::create(path).and_then(|file| {
+                    let mut output = io::BufWriter::new(file);
+                    runtime.save_snapshot(&mut output)?;
+                    output.flush()
+                });
+                if let Err(e) = saved {
+                    writeln!(output, "Failed to save to {}: {}", path, e)?;
+                }
+                None
+            }
+            ("load", Some(path)) => {
+                let loaded = match fs::File::open(path) {
+                    Ok(file) => self.runtime.load_snapshot(file),
+                    Err(e) => Err(Issue::new(
+                        bft::io::Error,
+                        &format!("Failed to open {}: {}", path, e),
+                    )),
+                };
+                match loaded {
+                    Ok(()) => self.show_position(output)?,
+                    Err(issue) => writeln!(output, "{}", issue)?,
+                }
+                None
+            }
+            ("l", None) | ("line", None) => {
+                self.show_position(output)?;
+                None
+            }
+            ("q", None) | ("quit", None) => return Ok(false),
+            ("h", None) | ("help", None) | ("", None) => {
+                writeln!(output, "{}", HELP)?;
+                None
+            }
+            _ => {
+                writeln!(output, "Unknown command '{}', type h for help", line)?;
+                None
+            }
+        };
+        if let Some(abort) = abort {
+            self.stopped(abort, input, output)?;
+        }
+        Ok(true)
+    }
+
+    // removes a breakpoint, watchpoint or condition
+    fn delete<W: Write>(&mut self, argument: &str, output: &mut W) -> io::Result<()> {
+        let removed = if let Some((line, col)) = parse_location(argument) {
+            self.runtime.remove_breakpoint(line, col)
+        } else if let Ok(cell) = argument.parse() {
+            self.runtime.remove_watchpoint(cell)
+        } else {
+            match runtime::Condition::parse(argument) {
+                Ok(condition) => self.runtime.remove_condition(&condition),
+                Err(message) => return writeln!(output, "{}", message),
+            }
+        };
+        if !removed {
+            writeln!(
+                output,
+                "There is no breakpoint, watchpoint or condition '{}'",
+                argument
+            )?;
+        }
+        Ok(())
+    }
+
+    // runs the code with the program's output going to output
+    fn run_code<W: Write>(
+        &mut self,
+        instr_cap: Option<usize>,
+        output: &mut W,
+    ) -> io::Result<Abort> {
+        let mut line_ended = self.line_ended;
+        let mut written = Ok(());
+        let abort = self.runtime.run(instr_cap, &mut |byte: u8| {
+            line_ended = byte == b'\n';
+            if written.is_ok() {
+                written = output.write_all(&[byte]);
+            }
+        });
+        written?;
+        output.flush()?;
+        self.line_ended = line_ended;
+        Ok(abort)
+    }
+
+    fn step<W: Write>(&mut self, count: usize, output: &mut W) -> io::Result<Option<Abort>> {
+        let mut left = count;
+        self.run_while(
+            |_| {
+                left = left.saturating_sub(1);
+                left > 0
+            },
+            output,
+        )
+    }
+
+    // runs one op at a time until keep_going returns false or the code stops on its own
+    fn run_while<F: FnMut(&debug::Runtime<D>) -> bool, W: Write>(
+        &mut self,
+        mut keep_going: F,
+        output: &mut W,
+    ) -> io::Result<Option<Abort>> {
+        loop {
+            match self.run_code(Some(1), output)? {
+                Abort::InstrCapped if keep_going(&self.runtime) => (),
+                abort => return Ok(Some(abort)),
+            }
+        }
+    }
+
+    fn stopped<R: BufRead, W: Write>(
+        &mut self,
+        abort: Abort,
+        input: &mut R,
+        output: &mut W,
+    ) -> io::Result<()> {
+        self.end_output_line(output)?;
+        match abort {
+            // the debugger never sets a deadline
+            Abort::InstrCapped | Abort::TimedOut => (),
+            Abort::Completed => writeln!(output, "The code has finished running")?,
+            Abort::AwaitingInput => {
+                write!(
+                    output,
+                    "The code is waiting for input, type a line to give it: "
+                )?;
+                output.flush()?;
+                let mut line = String::new();
+                match input.read_line(&mut line) {
+                    Ok(0) | Err(_) => {
+                        writeln!(output)?;
+                        self.runtime.close_input();
+                    }
+                    Ok(_) => self.runtime.queue_input_str(&line),
+                }
+            }
+            Abort::Breakpoint(span) => writeln!(output, "Stopped at a breakpoint at {}", span)?,
+            Abort::Watchpoint(span) => writeln!(
+                output,
+                "Stopped after {} changed a watched cell or made a condition true",
+                span
+            )?,
+            Abort::StartOfHistory => {
+                writeln!(output, "Got back to the oldest op that was remembered")?
+            }
+            Abort::Error(issue) | Abort::TapeFull(issue) => writeln!(output, "{}", issue)?,
+        }
+        self.show_position(output)
+    }
+
+    // the debugger's own output always starts on a new line
+    fn end_output_line<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        if !self.line_ended {
+            writeln!(output)?;
+            self.line_ended = true;
+        }
+        Ok(())
+    }
+
+    // shows the line of code with the next op highlighted, and the tape around the pointer
+    fn show_position<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        self.end_output_line(output)?;
+        if let Some(span) = self.runtime.next_span() {
+            let contents = span.src().contents();
+            let line = &contents[span.line_start_byte()..span.line_end_byte()];
+            let start = span.start_byte() - span.line_start_byte();
+            let end = cmp::min(span.end_byte(), span.line_end_byte()) - span.line_start_byte();
+            writeln!(
+                output,
+                "{} {}{}{}",
+                format!("{}:{}", span.line(), span.col()).dimmed(),
+                &line[..start],
+                line[start..end].bright_red().bold(),
+                &line[end..]
+            )?;
+        }
+        let runtime = &self.runtime;
+        let tape = show_tape(self.options.tape, runtime.get_ptr(), |i| {
+            runtime.get_cell(i)
+        });
+        writeln!(output, "{}", tape)
+    }
+}
+
+fn usage<W: Write>(usage: &str, output: &mut W) -> io::Result<Option<Abort>> {
+    writeln!(output, "Usage: {}", usage)?;
+    Ok(None)
+}
+
+// parses a LINE:COL breakpoint location
+fn parse_location(location: &str) -> Option<(u32, u32)> {
+    let mut parts = location.splitn(2, ':');
+    let line = parts.next()?.parse().ok()?;
+    let col = parts.next()?.parse().ok()?;
+    Some((line, col))
+}
+
+/// The cells around the pointer, with the one it is on highlighted
+pub fn show_tape<D: runtime::Cell, F: Fn(isize) -> D>(
+    tape: runtime::TapeModel,
+    ptr: isize,
+    get_cell: F,
+) -> String {
+    let first = match tape {
+        runtime::TapeModel::Infinite => ptr - TAPE_RADIUS,
+        _ => cmp::max(ptr - TAPE_RADIUS, 0),
+    };
+    let cells: Vec<String> = (first..=ptr + TAPE_RADIUS)
+        .map(|i| {
+            let cell = get_cell(i).to_string();
+            if i == ptr {
+                format!("[{}]", cell).bright_red().bold().to_string()
+            } else {
+                cell
+            }
+        })
+        .collect();
+    format!(
+        "{} {}",
+        format!("cells from {}:", first).dimmed(),
+        cells.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bft::source;
+    use std::rc::Rc;
+
+    // runs the commands on the code in a debugger, giving what it shows
+    fn debug(code: &str, commands: &str) -> String {
+        colored::control::set_override(false);
+        let options = Options::new_default();
+        let source = Rc::new(source::File::from_string(code.to_string()));
+        let tokens = source::lex(source);
+        let mut debugger = Debugger::<u8>::new(&options, &tokens);
+        let mut output = Vec::new();
+        debugger
+            .run_with(&mut commands.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    // the location of the next op each time it is shown
+    fn positions(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter_map(|line| line.trim_start_matches("(bft) ").split(' ').next())
+            .filter(|location| parse_location(location).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(parse_location("3:14"), Some((3, 14)));
+        assert_eq!(parse_location("0:0"), Some((0, 0)));
+        assert_eq!(parse_location("3"), None);
+        assert_eq!(parse_location("3:"), None);
+        assert_eq!(parse_location("a:1"), None);
+        assert_eq!(parse_location("3:14:1"), None);
+        assert_eq!(parse_location("-1:2"), None);
+    }
+
+    #[test]
+    fn next_runs_all_of_a_loop() {
+        let output = debug("+[->+<]>.", "s\nn\nn\n");
+        assert_eq!(positions(&output), ["0:0", "0:1", "0:7", "0:8"]);
+        assert!(output.ends_with("cells from 0: 0 [1] 0 0 0 0 0 0 0 0\n(bft) "));
+        // next is the same as step for an op that doesn't start a loop
+        let output = debug("+[->+<]>.", "s 2\nn\n");
+        assert_eq!(positions(&output), ["0:0", "0:2", "0:3"]);
+    }
+
+    #[test]
+    fn out_runs_to_the_end_of_the_loop() {
+        let output = debug("+[>++[-]<-]>", "o\ns 6\no\no\no\n");
+        assert_eq!(output.matches("The next op is not in a loop").count(), 2);
+        // out of the inner loop, then the outer one
+        assert_eq!(positions(&output), ["0:0", "0:6", "0:8", "0:11"]);
+    }
+
+    #[test]
+    fn deleting_stops() {
+        // without being deleted, each of these would stop the code a second time
+        let stops = [
+            ("b 0:4", "Stopped at a breakpoint at", "d 0:4"),
+            ("w 1", "Stopped after", "d 1"),
+            ("if cell[0] == 2", "Stopped after", "d cell[0] == 2"),
+        ];
+        for (add, stopped, delete) in &stops {
+            let commands = format!("{}\nc\n{}\nc\n", add, delete);
+            let output = debug("+++[>+<-]", &commands);
+            assert_eq!(output.matches(stopped).count(), 1, "{}", output);
+            assert!(output.contains("The code has finished running"));
+            assert!(!output.contains("There is no"));
+            let commands = format!("{}\nc\nc\n", add);
+            let output = debug("+++[>+<-]", &commands);
+            assert_eq!(output.matches(stopped).count(), 2, "{}", output);
+        }
+        let output = debug("+", "d 0:0\nd 0\nd ptr == 1\n");
+        assert_eq!(output.matches("There is no").count(), 3);
+    }
+
+    #[test]
+    fn issues_are_shown() {
+        let output = debug("+<", "c\nload /nonexistent/snapshot\n");
+        assert!(output.contains("Pointer moved left of the starting point"));
+        assert!(output.contains("Failed to open /nonexistent/snapshot"));
+    }
+}
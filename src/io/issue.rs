@@ -1,6 +1,5 @@
 extern crate colored;
 
-use self::colored::*;
 use std::fmt;
 
 use super::*;
@@ -47,11 +46,13 @@ impl fmt::Display for SpanDisplayData {
 }
 */
 
+/// A problem with code, found when checking or running it
 #[derive(PartialEq)]
 pub struct Issue {
     pub severity: Severity,
     pub span: Option<source::Span>,
     pub message: String,
+    pub notes: Vec<(source::Span, String)>, // other places in the code relevant to the issue
 }
 
 impl Issue {
@@ -60,16 +61,26 @@ impl Issue {
             severity,
             span: None,
             message: message.to_string(),
+            notes: Vec::new(),
         }
     }
+
+    pub fn with_note(mut self, span: source::Span, message: &str) -> Issue {
+        self.notes.push((span, message.to_string()));
+        self
+    }
 }
 
 impl fmt::Display for Issue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.span {
-            Some(s) => write!(f, "{}: {}:\n    {}", self.severity, s, self.message),
-            None => write!(f, "{}: {}", self.severity, &self.message),
+            Some(s) => write!(f, "{}: {}:\n    {}", self.severity, s, self.message)?,
+            None => write!(f, "{}: {}", self.severity, &self.message)?,
+        }
+        for (span, message) in &self.notes {
+            write!(f, "\n  Note: {}:\n    {}", span, message)?;
         }
+        Ok(())
     }
 }
 
@@ -77,13 +88,17 @@ impl fmt::Debug for Issue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{:?}: {}{}",
+            "{:?}: {}{}{}",
             self.severity,
             self.message,
             match &self.span {
                 Some(span) => format!(" at {:?}", span),
                 None => "".to_string(),
-            }
+            },
+            self.notes
+                .iter()
+                .map(|(span, message)| format!(" (note: {} at {:?})", message, span))
+                .collect::<String>()
         )
     }
 }
@@ -1,8 +1,8 @@
+//! Reporting issues found in code.
+
 mod issue;
-mod options;
 mod severity;
 
 pub use self::issue::Issue;
-pub use self::options::Options;
 pub use self::severity::Severity;
 pub use self::severity::Severity::*;
@@ -0,0 +1,9 @@
+//! Tools for linting, formatting and running Brainfuck code.
+//!
+//! Code is loaded into a [`source::File`], split into tokens by [`source::lex`] and checked by
+//! [`source::check_brackets`]. The tokens can then be run by either runtime in [`runtime`]. Every
+//! problem found along the way is reported as an [`io::Issue`] pointing at the code it is about.
+
+pub mod io;
+pub mod runtime;
+pub mod source;
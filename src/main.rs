@@ -1,21 +1,339 @@
-#[macro_use]
-mod io;
-mod runtime;
-mod source;
+extern crate bft;
+
+mod debugger;
+mod options;
+
+use bft::io;
+use bft::runtime;
+use bft::source;
+use options::{CellType, Options};
+
+use std::cmp;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+// the number of loops --profile reports
+const HOTTEST_LOOPS: usize = 10;
 
 fn main() {
-    let options = io::Options::new_default().with_cmd_line();
+    let options = Options::new_default().with_cmd_line();
     if let Some(ref path) = options.filepath {
-        let source = match source::File::open(path, &options) {
+        let source = match source::File::open(path) {
             Ok(s) => std::rc::Rc::new(s),
-            Err(i) => {
-                // io::message(io::Error, &i);
+            Err(e) => {
+                options.show_issue(&io::Issue::new(io::Error, &e));
                 ::std::process::exit(1);
             }
         };
-        let tokens = source::lex(source);
-        let mut runtime = runtime::debug::Runtime::<u8>::new();
-        runtime.add_tokens(&tokens);
-        runtime.run(None, &mut |c| print!("{}", c));
+        let tokens = source::lex(source.clone());
+        let issues = source::check_brackets(&tokens);
+        for issue in &issues {
+            options.show_issue(issue);
+        }
+        if issues.iter().any(|issue| issue.severity == io::Error) {
+            ::std::process::exit(1);
+        }
+        match options.cell_type {
+            CellType::U8 => run::<u8>(&options, &source, &tokens),
+            CellType::U16 => run::<u16>(&options, &source, &tokens),
+            CellType::U32 => run::<u32>(&options, &source, &tokens),
+            CellType::U64 => run::<u64>(&options, &source, &tokens),
+            CellType::I8 => run::<i8>(&options, &source, &tokens),
+            CellType::I16 => run::<i16>(&options, &source, &tokens),
+            CellType::I32 => run::<i32>(&options, &source, &tokens),
+            CellType::I64 => run::<i64>(&options, &source, &tokens),
+            CellType::Big => run::<runtime::BigInt>(&options, &source, &tokens),
+        }
+    }
+}
+
+// A runtime that code can be run on outside the debugger
+trait Runner<D> {
+    fn run_code<I: runtime::Io>(&mut self, instr_cap: Option<usize>, io: &mut I) -> runtime::Abort;
+    fn instr_count(&self) -> u64;
+    fn set_deadline(&mut self, deadline: Option<Instant>);
+    fn get_ptr(&self) -> isize;
+    fn get_cell(&self, i: isize) -> D;
+}
+
+impl<D: runtime::Cell> Runner<D> for runtime::fast::Runtime<D> {
+    fn run_code<I: runtime::Io>(&mut self, instr_cap: Option<usize>, io: &mut I) -> runtime::Abort {
+        self.run(instr_cap, io)
+    }
+
+    fn instr_count(&self) -> u64 {
+        self.instr_count()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.set_deadline(deadline);
+    }
+
+    fn get_ptr(&self) -> isize {
+        self.get_ptr()
+    }
+
+    fn get_cell(&self, i: isize) -> D {
+        self.get_cell(i)
+    }
+}
+
+// The debug runtime, telling an observer about each op it runs for --trace, --profile and
+// --coverage
+struct Observed<'a, D: runtime::Cell, O: 'a> {
+    runtime: runtime::debug::Runtime<D>,
+    observer: &'a mut O,
+}
+
+impl<'a, D: runtime::Cell, O: runtime::Observer<D>> Runner<D> for Observed<'a, D, O> {
+    fn run_code<I: runtime::Io>(&mut self, instr_cap: Option<usize>, io: &mut I) -> runtime::Abort {
+        self.runtime.run_with(instr_cap, io, self.observer)
+    }
+
+    fn instr_count(&self) -> u64 {
+        self.runtime.instr_count()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.runtime.set_deadline(deadline);
+    }
+
+    fn get_ptr(&self) -> isize {
+        self.runtime.get_ptr()
+    }
+
+    fn get_cell(&self, i: isize) -> D {
+        self.runtime.get_cell(i)
+    }
+}
+
+fn run<D: runtime::Cell>(options: &Options, source: &source::File, tokens: &[source::Token]) {
+    if options.debug {
+        debugger::Debugger::<D>::new(options, tokens).run();
+        return;
+    }
+    let mut issues = Vec::new();
+    let observed =
+        options.trace.is_some() || options.profile.is_some() || options.coverage.is_some();
+    let abort = if !observed && !options.detect_loops {
+        let mut runtime = runtime::fast::Runtime::<D>::new(tokens);
+        runtime.set_config(options.runtime_config());
+        run_to_end(options, &mut runtime)
+    } else {
+        run_observed::<D>(options, source, tokens, &mut issues)
+    };
+    let mut failed = !issues.is_empty();
+    let stopped = match abort {
+        runtime::Abort::Error(issue) | runtime::Abort::TapeFull(issue) => Some(issue),
+        runtime::Abort::InstrCapped => Some(io::Issue::new(
+            io::RuntimeError,
+            &format!(
+                "Stopped after running {} ops, the most --max-instrs allows",
+                options.max_instrs.unwrap()
+            ),
+        )),
+        runtime::Abort::TimedOut => {
+            let seconds = options.timeout.unwrap().as_secs_f64();
+            Some(io::Issue::new(
+                io::RuntimeError,
+                &format!(
+                    "Stopped after running for {} second{}, the longest --timeout allows",
+                    seconds,
+                    if seconds == 1.0 { "" } else { "s" }
+                ),
+            ))
+        }
+        _ => None,
+    };
+    if let Some(issue) = stopped {
+        options.show_issue(&issue);
+        failed = true;
+    }
+    for message in issues {
+        options.show_issue(&io::Issue::new(io::Error, &message));
+    }
+    if failed {
+        ::std::process::exit(1);
+    }
+}
+
+// Runs code on the debug runtime for --trace, --profile, --coverage and --detect-loops, then
+// writes what was recorded. Problems writing it are added to issues.
+fn run_observed<D: runtime::Cell>(
+    options: &Options,
+    source: &source::File,
+    tokens: &[source::Token],
+    issues: &mut Vec<String>,
+) -> runtime::Abort {
+    // files are opened before running, so a bad path is found before any time is spent
+    let tracer = options.trace.as_ref().map(|path| {
+        let mut tracer = runtime::Tracer::new(BufWriter::new(create_file(options, path)));
+        tracer = tracer.with_start(options.trace_after);
+        if let Some((first, last)) = options.trace_lines {
+            tracer = tracer.with_lines(first, last);
+        }
+        tracer
+    });
+    let profile_file = options
+        .profile
+        .as_ref()
+        .map(|path| create_file(options, path));
+    let profiler = profile_file
+        .as_ref()
+        .map(|_| runtime::Profiler::new(tokens, &options.runtime_config()));
+    let old_coverage = options
+        .coverage
+        .as_ref()
+        .map(|path| read_coverage(options, path));
+    let coverage = old_coverage
+        .as_ref()
+        .map(|_| runtime::Coverage::new(tokens, &options.runtime_config()));
+    let mut observer = ((tracer, profiler), coverage);
+    let mut runtime = runtime::debug::Runtime::<D>::new();
+    runtime.set_config(options.runtime_config());
+    runtime.add_tokens(tokens);
+    runtime.set_loop_detection(options.detect_loops);
+    let mut observed = Observed {
+        runtime,
+        observer: &mut observer,
+    };
+    let abort = run_to_end(options, &mut observed);
+    let instr_count = observed.runtime.instr_count();
+    let ((tracer, profiler), coverage) = observer;
+    if let (Some(tracer), Some(path)) = (tracer, options.trace.as_ref()) {
+        if let Err(e) = tracer.finish() {
+            issues.push(format!("Failed to write trace to '{}': {}", path, e));
+        }
+    }
+    if let (Some(profiler), Some(mut file), Some(path)) =
+        (profiler, profile_file, options.profile.as_ref())
+    {
+        eprint!("{}", show_hottest_loops(&profiler, instr_count));
+        if let Err(e) = file.write_all(profiler.annotate(source).as_bytes()) {
+            issues.push(format!("Failed to write profile to '{}': {}", path, e));
+        }
+    }
+    if let (Some(coverage), Some(mut records), Some(path)) =
+        (coverage, old_coverage, options.coverage.as_ref())
+    {
+        let record = coverage.lcov(&source.unwrap_path());
+        match records.iter_mut().find(|old| old.path == record.path) {
+            Some(old) => old.merge(&record),
+            None => records.push(record),
+        }
+        if let Err(e) = write_coverage(path, &records) {
+            issues.push(format!("Failed to write coverage to '{}': {}", path, e));
+        }
+    }
+    abort
+}
+
+// The records in an lcov file, or none if it does not exist yet. Exits if it can't be read.
+fn read_coverage(options: &Options, path: &str) -> Vec<runtime::LcovRecord> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            options.show_issue(&io::Issue::new(
+                io::Error,
+                &format!("Failed to read '{}': {}", path, e),
+            ));
+            ::std::process::exit(1);
+        }
+    };
+    match runtime::LcovRecord::read_all(&text) {
+        Ok(records) => records,
+        Err(issue) => {
+            options.show_issue(&issue);
+            ::std::process::exit(1);
+        }
+    }
+}
+
+fn write_coverage(path: &str, records: &[runtime::LcovRecord]) -> std::io::Result<()> {
+    let mut output = BufWriter::new(File::create(path)?);
+    for record in records {
+        record.write(&mut output)?;
+    }
+    output.flush()
+}
+
+// Creates a file to write to, exiting if it can't be
+fn create_file(options: &Options, path: &str) -> File {
+    match File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            options.show_issue(&io::Issue::new(
+                io::Error,
+                &format!("Failed to create '{}': {}", path, e),
+            ));
+            ::std::process::exit(1);
+        }
+    }
+}
+
+// The loops most ops were run in, and how much each was run
+fn show_hottest_loops(profiler: &runtime::Profiler, instr_count: u64) -> String {
+    let mut report = format!("{} ops were run. Hottest loops:\n", instr_count);
+    for profile in profiler.hottest_loops().iter().take(HOTTEST_LOOPS) {
+        report += &format!(
+            "  {}:{} to {}:{}: {} ops ({:.1}%), {} iterations, entered {} times\n",
+            profile.start.line(),
+            profile.start.col(),
+            profile.end.line(),
+            profile.end.col(),
+            profile.ops,
+            profile.ops as f64 * 100.0 / cmp::max(instr_count, 1) as f64,
+            profile.iterations,
+            profile.entered
+        );
+    }
+    report
+}
+
+// Runs code with stdin and stdout until it stops or reaches a limit, exiting if output can't be
+// written
+fn run_to_end<D: runtime::Cell, R: Runner<D>>(
+    options: &Options,
+    runtime: &mut R,
+) -> runtime::Abort {
+    runtime.set_deadline(options.timeout.map(|timeout| Instant::now() + timeout));
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+    let abort = loop {
+        // the limit is on every op run, not just the ones since the last '#'
+        let instr_cap = options
+            .max_instrs
+            .map(|max| max.saturating_sub(runtime.instr_count()));
+        if instr_cap == Some(0) {
+            break runtime::Abort::InstrCapped;
+        }
+        let instr_cap = instr_cap.map(|cap| cap.min(usize::MAX as u64) as usize);
+        match runtime.run_code(
+            instr_cap,
+            &mut runtime::Stream::new(stdin.lock(), &mut output),
+        ) {
+            // only '#' can stop here, since no breakpoints are set
+            runtime::Abort::Breakpoint(_) => {
+                let _ = output.flush();
+                let runtime = &*runtime;
+                let ptr = runtime.get_ptr();
+                eprintln!(
+                    "{}",
+                    debugger::show_tape(options.tape, ptr, |i| runtime.get_cell(i))
+                );
+            }
+            abort => break abort,
+        }
+    };
+    if output.flush().is_err() {
+        if let runtime::Abort::Error(issue) | runtime::Abort::TapeFull(issue) = abort {
+            options.show_issue(&issue);
+        }
+        ::std::process::exit(1);
     }
+    abort
 }
@@ -0,0 +1,375 @@
+use std;
+use std::time::Duration;
+
+extern crate clap;
+use self::clap::{App, Arg};
+
+use bft::io::Issue;
+use bft::runtime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    Big, // arbitrary precision, never overflows
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub filepath: Option<String>,        // code to run
+    pub fixup_file: bool,                // if to automatically fix problems found in the file
+    pub debug: bool,                     // if to run bft in debug mode
+    pub tape: runtime::TapeModel,        // the layout of memory when running code
+    pub overflow: runtime::Overflow,     // what happens when a cell goes out of range
+    pub cell_type: CellType,             // the type of number stored in each cell
+    pub eof: runtime::Eof,               // what reading input does after the end of it
+    pub encoding: runtime::Encoding,     // how input and output bytes are turned into cell values
+    pub debug_op: bool,                  // if '#' shows the tape, or stops the debugger
+    pub history_size: usize,             // bytes the debugger can use to run code backwards
+    pub trace: Option<String>,           // file to log each op run to
+    pub trace_lines: Option<(u32, u32)>, // the first and last line to log ops on
+    pub trace_after: u64,                // ops to run before logging any
+    pub profile: Option<String>,         // file to write the code to with the ops run on each line
+    pub coverage: Option<String>,        // lcov file to add the coverage of the code to
+    pub max_instrs: Option<u64>,         // the most ops to run before stopping
+    pub timeout: Option<Duration>,       // the longest to run for before stopping
+    pub tape_limit: Option<usize>,       // the most cells the tape can store
+    pub detect_loops: bool,              // if to stop at loops that will never end
+}
+
+impl Options {
+    pub fn new_default() -> Options {
+        Options {
+            filepath: None,
+            fixup_file: true,
+            debug: false,
+            tape: runtime::TapeModel::RightInfinite,
+            overflow: runtime::Overflow::Wrap,
+            cell_type: CellType::U8,
+            eof: runtime::Eof::Unchanged,
+            encoding: runtime::Encoding::Bytes,
+            debug_op: false,
+            history_size: 64 << 20,
+            trace: None,
+            trace_lines: None,
+            trace_after: 0,
+            profile: None,
+            coverage: None,
+            max_instrs: None,
+            timeout: None,
+            tape_limit: None,
+            detect_loops: false,
+        }
+    }
+
+    pub fn with_cmd_line(self) -> Options {
+        let app = App::new(env!("CARGO_PKG_NAME"))
+            .version(env!("CARGO_PKG_VERSION"))
+            .author(env!("CARGO_PKG_AUTHORS"))
+            .about(env!("CARGO_PKG_DESCRIPTION"))
+            .arg(
+                Arg::with_name("FILEPATH")
+                    .help("The input brainfuck source code")
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("DEBUG")
+                    .short("d")
+                    .long("debug")
+                    .help("Step through the code in an interactive debugger"),
+            )
+            .arg(
+                Arg::with_name("DEBUG_OP")
+                    .long("debug-op")
+                    .help(
+                        "Make '#' show the cells around the pointer, or stop at it like a \
+                         breakpoint in the debugger",
+                    ),
+            )
+            .arg(
+                Arg::with_name("HISTORY_SIZE")
+                    .long("history-size")
+                    .value_name("MEGABYTES")
+                    .default_value("64")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(_) => Ok(()),
+                        _ => Err("must be a number".to_string()),
+                    })
+                    .help(
+                        "Memory the debugger can use to remember the ops it has run, so it can run \
+                         them backwards",
+                    ),
+            )
+            .arg(
+                Arg::with_name("TRACE")
+                    .long("trace")
+                    .value_name("FILE")
+                    .conflicts_with("DEBUG")
+                    .help(
+                        "Log each op run to a file, as a line with the number of ops run before \
+                         it, its index in the code, the op, its line:column, the pointer and the \
+                         cell before and after it",
+                    ),
+            )
+            .arg(
+                Arg::with_name("TRACE_LINES")
+                    .long("trace-lines")
+                    .value_name("FIRST-LAST")
+                    .requires("TRACE")
+                    .validator(|s| match parse_range(&s) {
+                        Some(_) => Ok(()),
+                        _ => Err("must be two line numbers in order, like 10-20".to_string()),
+                    })
+                    .help("Only log ops on these lines, counting from 0"),
+            )
+            .arg(
+                Arg::with_name("TRACE_AFTER")
+                    .long("trace-after")
+                    .value_name("OPS")
+                    .requires("TRACE")
+                    .validator(|s| match s.parse::<u64>() {
+                        Ok(_) => Ok(()),
+                        _ => Err("must be a number".to_string()),
+                    })
+                    .help("Only log ops once this many have been run"),
+            )
+            .arg(
+                Arg::with_name("PROFILE")
+                    .long("profile")
+                    .value_name("FILE")
+                    .conflicts_with("DEBUG")
+                    .help(
+                        "Count the ops run, show the loops most ops were run in and write the \
+                         code to a file with the number of ops run on each line in front of it",
+                    ),
+            )
+            .arg(
+                Arg::with_name("COVERAGE")
+                    .long("coverage")
+                    .value_name("FILE")
+                    .conflicts_with("DEBUG")
+                    .help(
+                        "Add the lines run and the loops entered and skipped to an lcov coverage \
+                         file, so it has the coverage of every run it was given to",
+                    ),
+            )
+            .arg(
+                Arg::with_name("READONLY")
+                    .short("r")
+                    .long("readonly")
+                    .help("Don't apply fixes to source files if problems are found"),
+            )
+            .arg(
+                Arg::with_name("TAPE")
+                    .long("tape")
+                    .value_name("MODEL")
+                    .possible_values(&["right", "both", "fixed", "wrap"])
+                    .help(
+                        "Memory layout: infinite to the right of the starting cell (the default), \
+                         infinite in both directions, a fixed size or a fixed size that wraps around",
+                    ),
+            )
+            .arg(
+                Arg::with_name("TAPE_SIZE")
+                    .long("tape-size")
+                    .value_name("CELLS")
+                    .default_value("30000")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(size) if size > 0 => Ok(()),
+                        _ => Err("must be a positive number".to_string()),
+                    })
+                    .help("Number of cells on a fixed or wrapping tape"),
+            )
+            .arg(
+                Arg::with_name("MAX_TAPE")
+                    .long("max-tape")
+                    .value_name("CELLS")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(size) if size > 0 => Ok(()),
+                        _ => Err("must be a positive number".to_string()),
+                    })
+                    .help(
+                        "Most cells the tape can grow to, stopping the code if the pointer moves \
+                         past them",
+                    ),
+            )
+            .arg(
+                Arg::with_name("MAX_INSTRS")
+                    .long("max-instrs")
+                    .value_name("COUNT")
+                    .conflicts_with("DEBUG")
+                    .validator(|s| match s.parse::<u64>() {
+                        Ok(count) if count > 0 => Ok(()),
+                        _ => Err("must be a positive number".to_string()),
+                    })
+                    .help(
+                        "Most ops to run before stopping the code, counting each op every time it \
+                         is run. A few more can be run, as ops are run together where they can be",
+                    ),
+            )
+            .arg(
+                Arg::with_name("TIMEOUT")
+                    .long("timeout")
+                    .value_name("SECONDS")
+                    .conflicts_with("DEBUG")
+                    .validator(|s| match s.parse().map(Duration::try_from_secs_f64) {
+                        Ok(Ok(time)) if time > Duration::ZERO => Ok(()),
+                        _ => Err("must be a positive number".to_string()),
+                    })
+                    .help(
+                        "Longest to run the code for before stopping it. Time spent waiting for \
+                         input counts, but code that is waiting for input is not stopped",
+                    ),
+            )
+            .arg(
+                Arg::with_name("DETECT_LOOPS")
+                    .long("detect-loops")
+                    .help(
+                        "Stop with an error at a loop that will never end, which is found when \
+                         an iteration gets back to the state it started in without any input or \
+                         output. Code runs more slowly with this",
+                    ),
+            )
+            .arg(
+                Arg::with_name("OVERFLOW")
+                    .long("overflow")
+                    .value_name("MODE")
+                    .possible_values(&["wrap", "saturate", "trap"])
+                    .help(
+                        "What happens when a cell goes past its largest or smallest value: wrap \
+                         around (the default), stay at the limit or stop with an error",
+                    ),
+            )
+            .arg(
+                Arg::with_name("CELL_SIZE")
+                    .long("cell-size")
+                    .value_name("TYPE")
+                    .possible_values(&["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "big"])
+                    .help(
+                        "Type of number stored in each cell, unsigned 8 bit by default. Big cells \
+                         have no limit on their size, so they never overflow",
+                    ),
+            )
+            .arg(
+                Arg::with_name("EOF")
+                    .long("eof")
+                    .value_name("BEHAVIOR")
+                    .possible_values(&["unchanged", "zero", "minus-one", "error"])
+                    .help(
+                        "What reading input does after the end of it: leave the cell unchanged \
+                         (the default), set it to 0, set it to -1 (the largest value for unsigned \
+                         cells) or stop with an error",
+                    ),
+            )
+            .arg(
+                Arg::with_name("ENCODING")
+                    .long("encoding")
+                    .value_name("ENCODING")
+                    .possible_values(&["bytes", "utf8"])
+                    .help(
+                        "How input and output are read and written: a byte at a time (the \
+                         default), or as UTF-8 with a unicode code point in each cell",
+                    ),
+            );
+        let matches = app.clone().get_matches();
+        let mut options = self;
+        options.filepath = matches.value_of("FILEPATH").map(|s| s.to_string());
+        if matches.is_present("DEBUG") {
+            options.debug = true;
+        }
+        if matches.is_present("DEBUG_OP") {
+            options.debug_op = true;
+        }
+        if matches.is_present("DETECT_LOOPS") {
+            options.detect_loops = true;
+        }
+        if matches.is_present("READONLY") {
+            options.fixup_file = false;
+        }
+        let history_size: usize = matches.value_of("HISTORY_SIZE").unwrap().parse().unwrap();
+        options.history_size = history_size << 20;
+        options.trace = matches.value_of("TRACE").map(|s| s.to_string());
+        options.trace_lines = matches.value_of("TRACE_LINES").and_then(parse_range);
+        options.profile = matches.value_of("PROFILE").map(|s| s.to_string());
+        options.coverage = matches.value_of("COVERAGE").map(|s| s.to_string());
+        options.max_instrs = matches.value_of("MAX_INSTRS").map(|s| s.parse().unwrap());
+        options.timeout = matches
+            .value_of("TIMEOUT")
+            .map(|s| Duration::from_secs_f64(s.parse().unwrap()));
+        options.tape_limit = matches.value_of("MAX_TAPE").map(|s| s.parse().unwrap());
+        if let Some(ops) = matches.value_of("TRACE_AFTER") {
+            options.trace_after = ops.parse().unwrap();
+        }
+        let tape_size = matches.value_of("TAPE_SIZE").unwrap().parse().unwrap();
+        options.tape = match matches.value_of("TAPE") {
+            Some("both") => runtime::TapeModel::Infinite,
+            Some("fixed") => runtime::TapeModel::Bounded(tape_size),
+            Some("wrap") => runtime::TapeModel::Wrapping(tape_size),
+            _ => runtime::TapeModel::RightInfinite,
+        };
+        options.overflow = match matches.value_of("OVERFLOW") {
+            Some("saturate") => runtime::Overflow::Saturate,
+            Some("trap") => runtime::Overflow::Trap,
+            _ => runtime::Overflow::Wrap,
+        };
+        options.cell_type = match matches.value_of("CELL_SIZE") {
+            Some("u16") => CellType::U16,
+            Some("u32") => CellType::U32,
+            Some("u64") => CellType::U64,
+            Some("i8") => CellType::I8,
+            Some("i16") => CellType::I16,
+            Some("i32") => CellType::I32,
+            Some("i64") => CellType::I64,
+            Some("big") => CellType::Big,
+            _ => CellType::U8,
+        };
+        options.eof = match matches.value_of("EOF") {
+            Some("zero") => runtime::Eof::Zero,
+            Some("minus-one") => runtime::Eof::MinusOne,
+            Some("error") => runtime::Eof::Error,
+            _ => runtime::Eof::Unchanged,
+        };
+        options.encoding = match matches.value_of("ENCODING") {
+            Some("utf8") => runtime::Encoding::Utf8,
+            _ => runtime::Encoding::Bytes,
+        };
+        if options.filepath.is_none() {
+            app.write_help(&mut std::io::stdout())
+                .expect("failed to write to stdout");
+            println!();
+        }
+        options
+    }
+
+    pub fn runtime_config(&self) -> runtime::Config {
+        runtime::Config {
+            tape: self.tape,
+            overflow: self.overflow,
+            eof: self.eof,
+            encoding: self.encoding,
+            debug_op: self.debug_op,
+            tape_limit: self.tape_limit,
+        }
+    }
+
+    pub fn show_issue(&self, issue: &Issue) {
+        eprintln!("{}", issue);
+    }
+}
+
+// parses a range of lines like "10-20", which can't end before it starts
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let mut parts = range.splitn(2, '-');
+    let first = parts.next()?.parse().ok()?;
+    let last = parts.next()?.parse().ok()?;
+    if first > last {
+        return None;
+    }
+    Some((first, last))
+}
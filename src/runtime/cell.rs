@@ -0,0 +1,220 @@
+extern crate num_bigint;
+extern crate num_traits;
+
+pub use self::num_bigint::BigInt;
+use self::num_traits::{FromPrimitive, ToPrimitive};
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+/// Everything a runtime needs from the type it stores in each cell
+pub trait Cell: 'static + Clone + PartialEq + PartialOrd + Debug + Display + FromStr {
+    /// if the cell can only hold a fixed range of values, an unbounded cell never overflows
+    const BOUNDED: bool;
+
+    fn zero() -> Self;
+
+    fn one() -> Self {
+        Self::from_byte(1)
+    }
+
+    /// The value stored for a byte of input. Signed cells store bytes above 127 as negative
+    /// numbers, the same as casting the byte to a signed byte would.
+    fn from_byte(byte: u8) -> Self;
+
+    /// The byte a cell is output as, which is the lowest 8 bits of its two's complement value
+    fn to_byte(&self) -> u8;
+
+    /// None if the code point is too large for the cell
+    fn from_code_point(code_point: u32) -> Option<Self>;
+
+    /// None if the value is negative or too large to be a code point
+    fn to_code_point(&self) -> Option<u32>;
+
+    /// The exact value of the cell, so cells of any type can be compared with other numbers
+    fn to_bigint(&self) -> BigInt;
+
+    /// How many times one has to be subtracted from the cell, wrapping around, for it to get to
+    /// zero. An unbounded cell that is below zero never gets there, and gives u64::MAX, the same as
+    /// one too large for the count to fit.
+    fn decrements_to_zero(&self) -> u64;
+
+    /// Roughly how many bytes the value uses on the heap, which is 0 for a fixed width cell
+    fn heap_size(&self) -> usize;
+
+    fn wrapping_add(&self, amount: &Self) -> Self;
+    fn wrapping_sub(&self, amount: &Self) -> Self;
+    fn wrapping_mul(&self, amount: &Self) -> Self;
+    fn saturating_add(&self, amount: &Self) -> Self;
+    fn saturating_sub(&self, amount: &Self) -> Self;
+    fn checked_add(&self, amount: &Self) -> Option<Self>;
+    fn checked_sub(&self, amount: &Self) -> Option<Self>;
+}
+
+fn from_byte<D: FromPrimitive>(byte: u8) -> D {
+    D::from_u8(byte).or_else(|| D::from_i8(byte as i8)).unwrap()
+}
+
+// each type is given with the unsigned type of the same width
+macro_rules! fixed_width_cell {
+    ($($t:ty: $unsigned:ty),*) => {$(
+        impl Cell for $t {
+            const BOUNDED: bool = true;
+
+            fn zero() -> $t {
+                0
+            }
+
+            fn from_byte(byte: u8) -> $t {
+                from_byte(byte)
+            }
+
+            fn to_byte(&self) -> u8 {
+                *self as u8
+            }
+
+            fn from_code_point(code_point: u32) -> Option<$t> {
+                FromPrimitive::from_u32(code_point)
+            }
+
+            fn to_code_point(&self) -> Option<u32> {
+                ToPrimitive::to_u32(self)
+            }
+
+            fn to_bigint(&self) -> BigInt {
+                BigInt::from(*self)
+            }
+
+            fn decrements_to_zero(&self) -> u64 {
+                *self as $unsigned as u64
+            }
+
+            fn heap_size(&self) -> usize {
+                0
+            }
+
+            fn wrapping_add(&self, amount: &$t) -> $t {
+                <$t>::wrapping_add(*self, *amount)
+            }
+
+            fn wrapping_sub(&self, amount: &$t) -> $t {
+                <$t>::wrapping_sub(*self, *amount)
+            }
+
+            fn wrapping_mul(&self, amount: &$t) -> $t {
+                <$t>::wrapping_mul(*self, *amount)
+            }
+
+            fn saturating_add(&self, amount: &$t) -> $t {
+                <$t>::saturating_add(*self, *amount)
+            }
+
+            fn saturating_sub(&self, amount: &$t) -> $t {
+                <$t>::saturating_sub(*self, *amount)
+            }
+
+            fn checked_add(&self, amount: &$t) -> Option<$t> {
+                <$t>::checked_add(*self, *amount)
+            }
+
+            fn checked_sub(&self, amount: &$t) -> Option<$t> {
+                <$t>::checked_sub(*self, *amount)
+            }
+        }
+    )*};
+}
+
+fixed_width_cell!(u8: u8, u16: u16, u32: u32, u64: u64, i8: u8, i16: u16, i32: u32, i64: u64);
+
+/// Arbitrary precision cells never overflow, so every overflow mode behaves the same
+impl Cell for BigInt {
+    const BOUNDED: bool = false;
+
+    fn zero() -> BigInt {
+        BigInt::from(0)
+    }
+
+    fn from_byte(byte: u8) -> BigInt {
+        BigInt::from(byte)
+    }
+
+    fn to_byte(&self) -> u8 {
+        self.to_signed_bytes_le()[0]
+    }
+
+    fn from_code_point(code_point: u32) -> Option<BigInt> {
+        Some(BigInt::from(code_point))
+    }
+
+    fn to_code_point(&self) -> Option<u32> {
+        ToPrimitive::to_u32(self)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        self.clone()
+    }
+
+    fn decrements_to_zero(&self) -> u64 {
+        ToPrimitive::to_u64(self).unwrap_or(u64::MAX)
+    }
+
+    // the digits are stored as 64 bit words
+    fn heap_size(&self) -> usize {
+        self.bits().div_ceil(64) as usize * 8
+    }
+
+    fn wrapping_add(&self, amount: &BigInt) -> BigInt {
+        self + amount
+    }
+
+    fn wrapping_sub(&self, amount: &BigInt) -> BigInt {
+        self - amount
+    }
+
+    fn wrapping_mul(&self, amount: &BigInt) -> BigInt {
+        self * amount
+    }
+
+    fn saturating_add(&self, amount: &BigInt) -> BigInt {
+        self + amount
+    }
+
+    fn saturating_sub(&self, amount: &BigInt) -> BigInt {
+        self - amount
+    }
+
+    fn checked_add(&self, amount: &BigInt) -> Option<BigInt> {
+        Some(self + amount)
+    }
+
+    fn checked_sub(&self, amount: &BigInt) -> Option<BigInt> {
+        Some(self - amount)
+    }
+}
+
+/// What happens when a cell is changed past the largest or smallest value it can hold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Wrap,     // continue from the other end of the range
+    Saturate, // stay at the largest or smallest value
+    Trap,     // stop with an error
+}
+
+impl Overflow {
+    /// returns None if the result is out of range and overflow is trapped
+    pub fn add<D: Cell>(self, value: &D, amount: &D) -> Option<D> {
+        match self {
+            Overflow::Wrap => Some(value.wrapping_add(amount)),
+            Overflow::Saturate => Some(value.saturating_add(amount)),
+            Overflow::Trap => value.checked_add(amount),
+        }
+    }
+
+    /// returns None if the result is out of range and overflow is trapped
+    pub fn sub<D: Cell>(self, value: &D, amount: &D) -> Option<D> {
+        match self {
+            Overflow::Wrap => Some(value.wrapping_sub(amount)),
+            Overflow::Saturate => Some(value.saturating_sub(amount)),
+            Overflow::Trap => value.checked_sub(amount),
+        }
+    }
+}
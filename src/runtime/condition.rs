@@ -0,0 +1,324 @@
+extern crate num_traits;
+
+use self::num_traits::ToPrimitive;
+use super::*;
+use std::fmt;
+
+/// An expression over the state of a runtime, such as `ptr > 300 and cell[ptr] == 10`. It can use
+/// numbers, `ptr`, `instrs` (the number of instructions run), `cell[INDEX]`, `+`, `-`, the
+/// comparisons `==`, `!=`, `<`, `<=`, `>`, `>=`, `and`, `or`, `not` and parentheses. Numbers that
+/// are not 0 count as true, and comparisons give 1 or 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(BigInt),
+    Ptr,
+    Instrs,
+    Cell(Box<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(BigInt),
+    Word(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "'{}'", n),
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+            Token::End => write!(f, "the end of the condition"),
+        }
+    }
+}
+
+// longer symbols come first, so '<=' is not read as '<' followed by '='
+const SYMBOLS: [&str; 12] = [
+    "==", "!=", "<=", ">=", "<", ">", "+", "-", "(", ")", "[", "]",
+];
+
+fn lex(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(rest[..len].parse().unwrap()));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..len].to_string()));
+            len
+        } else {
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                Some(symbol) => {
+                    tokens.push(Token::Symbol(symbol));
+                    symbol.len()
+                }
+                None => return Err(format!("Unexpected '{}' in condition", c)),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    tokens.push(Token::End);
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next]
+    }
+
+    // takes the next token if it is the given symbol or word
+    fn accept(&mut self, expected: &str) -> bool {
+        let found = match self.peek() {
+            Token::Symbol(symbol) => *symbol == expected,
+            Token::Word(word) => word == expected,
+            _ => false,
+        };
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        if self.accept(expected) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' but found {}", expected, self.peek()))
+        }
+    }
+
+    // parses operands separated by any of the given operators, which all have the same precedence
+    fn binary<F>(&mut self, ops: &[&'static str], operand: F) -> Result<Expr, String>
+    where
+        F: Fn(&mut Parser) -> Result<Expr, String>,
+    {
+        let mut expr = operand(self)?;
+        'outer: loop {
+            for op in ops {
+                if self.accept(op) {
+                    expr = Expr::Binary(op, Box::new(expr), Box::new(operand(self)?));
+                    continue 'outer;
+                }
+            }
+            return Ok(expr);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&["or"], |p| p.and())
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&["and"], |p| p.not())
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.accept("not") {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.compare()
+        }
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        for op in &["==", "!=", "<=", ">=", "<", ">"] {
+            if self.accept(op) {
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(self.sum()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&["+", "-"], |p| p.unary())
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.accept("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let token = self.peek().clone();
+        self.next += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Word(ref word) if word == "ptr" => Ok(Expr::Ptr),
+            Token::Word(ref word) if word == "instrs" => Ok(Expr::Instrs),
+            Token::Word(ref word) if word == "cell" => {
+                self.expect("[")?;
+                let index = self.or()?;
+                self.expect("]")?;
+                Ok(Expr::Cell(Box::new(index)))
+            }
+            Token::Symbol("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            token => Err(format!(
+                "Expected a number, 'ptr', 'instrs', 'cell' or '(' but found {}",
+                token
+            )),
+        }
+    }
+}
+
+fn truth(value: bool) -> BigInt {
+    BigInt::from(value as u8)
+}
+
+impl Expr {
+    fn eval<F: Fn(isize) -> BigInt>(&self, ptr: isize, instrs: u64, cell: &F) -> BigInt {
+        let zero = BigInt::from(0);
+        match self {
+            Expr::Number(n) => n.clone(),
+            Expr::Ptr => BigInt::from(ptr),
+            Expr::Instrs => BigInt::from(instrs),
+            // an index too large to be on any tape is always a zero cell
+            Expr::Cell(index) => match index.eval(ptr, instrs, cell).to_isize() {
+                Some(index) => cell(index),
+                None => zero,
+            },
+            Expr::Neg(expr) => -expr.eval(ptr, instrs, cell),
+            Expr::Not(expr) => truth(expr.eval(ptr, instrs, cell) == zero),
+            Expr::Binary("and", left, right) => {
+                truth(left.eval(ptr, instrs, cell) != zero && right.eval(ptr, instrs, cell) != zero)
+            }
+            Expr::Binary("or", left, right) => {
+                truth(left.eval(ptr, instrs, cell) != zero || right.eval(ptr, instrs, cell) != zero)
+            }
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(ptr, instrs, cell);
+                let right = right.eval(ptr, instrs, cell);
+                match *op {
+                    "+" => left + right,
+                    "-" => left - right,
+                    "==" => truth(left == right),
+                    "!=" => truth(left != right),
+                    "<" => truth(left < right),
+                    "<=" => truth(left <= right),
+                    ">" => truth(left > right),
+                    ">=" => truth(left >= right),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// Returns a message saying what is wrong if text is not a valid condition
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let mut parser = Parser {
+            tokens: lex(text)?,
+            next: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            Token::End => Ok(Condition { expr }),
+            token => Err(format!(
+                "Expected the end of the condition but found {}",
+                token
+            )),
+        }
+    }
+
+    pub(crate) fn is_met<F: Fn(isize) -> BigInt>(&self, ptr: isize, instrs: u64, cell: F) -> bool {
+        self.expr.eval(ptr, instrs, &cell) != BigInt::from(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> bool {
+        let cells = [3, 10, 0, 7];
+        let condition = Condition::parse(text).unwrap();
+        condition.is_met(1, 20, |i| {
+            BigInt::from(*cells.get(i as usize).unwrap_or(&0))
+        })
+    }
+
+    #[test]
+    fn values() {
+        assert!(eval("ptr == 1"));
+        assert!(eval("instrs == 20"));
+        assert!(eval("cell[ptr] == 10"));
+        assert!(eval("cell[ptr + 2] == cell[0] + 4"));
+        assert!(eval("-ptr < 0"));
+        assert!(eval("cell[1]"));
+        assert!(!eval("cell[2]"));
+        assert!(eval("cell[123456789012345678901234567890] == 0"));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(eval(
+            "ptr != 2 and ptr <= 1 and ptr >= 1 and ptr < 2 and ptr > 0"
+        ));
+        assert!(!eval("ptr > 1"));
+        assert!(eval("(ptr == 1) + (instrs == 20) == 2"));
+    }
+
+    #[test]
+    fn logic() {
+        assert!(eval("ptr > 300 or cell[ptr] == 10"));
+        assert!(!eval("ptr > 300 and cell[ptr] == 10"));
+        assert!(eval("not ptr > 300"));
+        assert!(eval("ptr == 1 or ptr == 2 and ptr == 3"));
+        assert!(!eval("(ptr == 1 or ptr == 2) and ptr == 3"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Condition::parse("cell[1"),
+            Err("Expected ']' but found the end of the condition".to_string())
+        );
+        assert_eq!(
+            Condition::parse("ptr = 1"),
+            Err("Unexpected '=' in condition".to_string())
+        );
+        assert_eq!(
+            Condition::parse("ptr 1"),
+            Err("Expected the end of the condition but found '1'".to_string())
+        );
+        assert_eq!(
+            Condition::parse("x > 1"),
+            Err("Expected a number, 'ptr', 'instrs', 'cell' or '(' but found 'x'".to_string())
+        );
+        assert_eq!(
+            Condition::parse(""),
+            Err(
+                "Expected a number, 'ptr', 'instrs', 'cell' or '(' but found the end of the \
+                 condition"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+
+/// Settings that decide how code behaves when it is run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub tape: TapeModel,
+    pub overflow: Overflow,
+    pub eof: Eof,
+    pub encoding: Encoding,
+    pub debug_op: bool,            // if '#' stops the code with Abort::Breakpoint
+    pub tape_limit: Option<usize>, // the most cells the tape can store, see Tape::set_limit
+}
+
+/// What ',' does once all input has been read and no more is coming
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eof {
+    Unchanged, // leave the cell as it is
+    Zero,      // set the cell to 0
+    MinusOne,  // set the cell to -1, which is the largest value for unsigned cells
+    Error,     // stop with an error
+}
+
+impl Eof {
+    /// returns the value the cell is set to, or None if reading past the end is an error
+    pub fn apply<D: Cell>(self, value: D) -> Option<D> {
+        match self {
+            Eof::Unchanged => Some(value),
+            Eof::Zero => Some(D::zero()),
+            Eof::MinusOne => Some(D::zero().wrapping_sub(&D::one())),
+            Eof::Error => None,
+        }
+    }
+}
+
+impl Config {
+    pub fn new_default() -> Config {
+        Config {
+            tape: TapeModel::RightInfinite,
+            overflow: Overflow::Wrap,
+            eof: Eof::Unchanged,
+            encoding: Encoding::Bytes,
+            debug_op: false,
+            tape_limit: None,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use super::*;
+use io::Issue;
+use source::{Span, Token};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Records which ops are run by debug::Runtime::run_with, and which loops are entered or skipped
+pub struct Coverage {
+    code: Vec<(Op, Span)>,
+    counts: Vec<u64>,  // times each op in code has been run
+    skipped: Vec<u64>, // for each '[', times it has been run on a zero cell
+    debug_op: bool,    // if '#' is an op, rather than a comment
+}
+
+/// Line and loop coverage of a source file, as it is written in an lcov report. Records for the
+/// same file can be merged, to get the coverage of several runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LcovRecord {
+    pub path: String,
+    pub lines: BTreeMap<u32, u64>, // times the ops on each line were run, with lines counting from 1
+    // times each branch was taken, by the line, block and branch number, or None if the block was
+    // never reached. Each loop is a block numbered by the column of its '[', with branch 0 being
+    // entering it and branch 1 skipping it.
+    pub branches: BTreeMap<(u32, u32, u32), Option<u64>>,
+}
+
+impl Coverage {
+    /// Records coverage of the ops in tokens, which should be the same code the runtime runs with
+    /// the same config
+    pub fn new(tokens: &[Token], config: &Config) -> Coverage {
+        let code = ops(tokens);
+        Coverage {
+            counts: vec![0; code.len()],
+            skipped: vec![0; code.len()],
+            code,
+            debug_op: config.debug_op,
+        }
+    }
+
+    /// The coverage so far, as a record for the file at path. A line counts as run as many times
+    /// as the op on it that was run the most.
+    pub fn lcov(&self, path: &str) -> LcovRecord {
+        let mut record = LcovRecord::new(path);
+        for (instr, (op, span)) in self.code.iter().enumerate() {
+            if *op == Op::Debug && !self.debug_op {
+                continue;
+            }
+            let line = span.line() + 1;
+            let count = self.counts[instr];
+            let line_count = record.lines.entry(line).or_insert(0);
+            *line_count = (*line_count).max(count);
+            if *op == Op::Start {
+                let reached = |taken| if count > 0 { Some(taken) } else { None };
+                let skipped = self.skipped[instr];
+                let block = span.col();
+                record
+                    .branches
+                    .insert((line, block, 0), reached(count - skipped));
+                record.branches.insert((line, block, 1), reached(skipped));
+            }
+        }
+        record
+    }
+}
+
+impl<D: Cell> Observer<D> for Coverage {
+    fn op_run(&mut self, event: &Event<D>) {
+        if event.instr >= self.counts.len() {
+            return;
+        }
+        self.counts[event.instr] += 1;
+        if event.op == Op::Start && *event.before == D::zero() {
+            self.skipped[event.instr] += 1;
+        }
+    }
+}
+
+fn invalid(line: usize, message: &str) -> Issue {
+    Issue::new(
+        ::io::Error,
+        &format!("Coverage is not valid lcov: line {}: {}", line + 1, message),
+    )
+}
+
+// parses the first count comma separated numbers in values, which come after the ':' of the
+// line. The last can be '-', and anything after them, like the checksum at the end of a DA line,
+// is ignored.
+fn parse_numbers(
+    i: usize,
+    line: &str,
+    values: &str,
+    count: usize,
+) -> Result<(Vec<u32>, Option<u64>), Issue> {
+    let error = || invalid(i, &format!("'{}' should have {} numbers", line, count));
+    let fields: Vec<&str> = values.split(',').take(count).collect();
+    if fields.len() < count {
+        return Err(error());
+    }
+    let first = fields[..count - 1]
+        .iter()
+        .map(|field| field.parse().ok())
+        .collect::<Option<Vec<u32>>>()
+        .ok_or_else(error)?;
+    let last = match fields[count - 1] {
+        "-" => None,
+        field => Some(field.parse().map_err(|_| error())?),
+    };
+    Ok((first, last))
+}
+
+impl LcovRecord {
+    pub fn new(path: &str) -> LcovRecord {
+        LcovRecord {
+            path: path.to_string(),
+            lines: BTreeMap::new(),
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the counts from other, which should be of the same file
+    pub fn merge(&mut self, other: &LcovRecord) {
+        for (line, count) in &other.lines {
+            *self.lines.entry(*line).or_insert(0) += count;
+        }
+        for (branch, taken) in &other.branches {
+            let merged = self.branches.entry(*branch).or_insert(None);
+            *merged = match (*merged, *taken) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            };
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut output: W) -> ::std::io::Result<()> {
+        writeln!(output, "TN:\nSF:{}", self.path)?;
+        for ((line, block, branch), taken) in &self.branches {
+            match taken {
+                Some(taken) => writeln!(output, "BRDA:{},{},{},{}", line, block, branch, taken)?,
+                None => writeln!(output, "BRDA:{},{},{},-", line, block, branch)?,
+            }
+        }
+        let taken = self.branches.values().filter(|t| t.unwrap_or(0) > 0);
+        writeln!(output, "BRF:{}", self.branches.len())?;
+        writeln!(output, "BRH:{}", taken.count())?;
+        for (line, count) in &self.lines {
+            writeln!(output, "DA:{},{}", line, count)?;
+        }
+        writeln!(output, "LF:{}", self.lines.len())?;
+        let hit = self.lines.values().filter(|count| **count > 0);
+        writeln!(output, "LH:{}", hit.count())?;
+        writeln!(output, "end_of_record")
+    }
+
+    /// Reads every record in an lcov report. Only the lines and branches are read, so anything
+    /// else in the report is left out of the records.
+    pub fn read_all(text: &str) -> Result<Vec<LcovRecord>, Issue> {
+        let mut records = Vec::new();
+        let mut record: Option<LcovRecord> = None;
+        for (i, line) in text.lines().enumerate() {
+            let (kind, values) = match line.find(':') {
+                Some(colon) => (&line[..colon], &line[colon + 1..]),
+                None => (line, ""),
+            };
+            if kind == "SF" {
+                if record.is_some() {
+                    return Err(invalid(i, "expected 'end_of_record' before 'SF'"));
+                }
+                record = Some(LcovRecord::new(values));
+                continue;
+            }
+            let current = match record {
+                Some(ref mut current) => current,
+                None if kind == "TN" || line.is_empty() => continue,
+                None => return Err(invalid(i, "expected 'SF' to start a record")),
+            };
+            match kind {
+                "DA" => {
+                    let (line, count) = parse_numbers(i, line, values, 2)?;
+                    *current.lines.entry(line[0]).or_insert(0) += count.unwrap_or(0);
+                }
+                "BRDA" => {
+                    let (branch, taken) = parse_numbers(i, line, values, 4)?;
+                    current
+                        .branches
+                        .insert((branch[0], branch[1], branch[2]), taken);
+                }
+                "end_of_record" => records.push(record.take().unwrap()),
+                _ => (),
+            }
+        }
+        match record {
+            Some(_) => Err(invalid(
+                text.lines().count() - 1,
+                "expected 'end_of_record' at the end",
+            )),
+            None => Ok(records),
+        }
+    }
+}
@@ -1,166 +1,474 @@
-extern crate num_traits;
-
-use std::char;
-
-use self::num_traits::*;
-
+use super::encoding::InputResult;
+use super::loop_detector::LoopDetector;
+use super::snapshot::{hash_code, Snapshot};
+use super::stream::InputBuffer;
 use super::*;
-use io;
 use io::Issue;
 use source::Span;
 use source::Token;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::mem;
+use std::time::Instant;
 
+/// Runs code one op at a time, exactly as it is written
 pub struct Runtime<D> {
     code: Vec<(Op, Span)>,
+    jumps: Vec<Option<usize>>, // index of the matching bracket for each '[' and ']' in code
+    open: Vec<usize>,          // '['s that have not been matched by a ']' yet
     stack: Vec<usize>,
-    data: Vec<D>,
-    ptr: usize,
-    input_buffer: Vec<char>,
+    config: Config,
+    tape: Tape<D>,
+    ptr: isize,
+    input: InputBuffer,
+    breakpoints: Vec<(u32, u32)>, // line and column of each op to stop at
+    watchpoints: Vec<isize>,      // cells to stop at when they change
+    conditions: Vec<(Condition, bool)>, // conditions to stop at, and if each was met last time
+    stopped: Option<Stop>, // where the last run stopped, so the next one doesn't stop there again
+    instr_count: u64,
+    deadline: Option<Instant>,
+    loop_detector: Option<LoopDetector<D>>,
+    history: VecDeque<Step<D>>, // the ops that have been run, most recent last
+    history_size: usize,        // bytes used by history
+    history_limit: usize,       // most bytes history can use before the oldest ops are forgotten
+}
+
+// What an op changed, so it can be undone
+struct Step<D> {
+    instr: usize,          // the op, which was on top of the stack before it was run
+    stack_len: usize,      // length of the stack before the op was run
+    popped: Option<usize>, // for a ']', the start of the loop under it on the stack
+    ptr: isize,
+    cell: Option<D>, // for an op that can change the cell at ptr, the value it had before
+    input: Vec<u8>,  // the bytes of input the op took
+}
+
+impl<D: Cell> Step<D> {
+    // roughly the memory used by the step, including what a big cell uses on the heap
+    fn size(&self) -> usize {
+        mem::size_of::<Step<D>>() + self.input.len() + self.cell.as_ref().map_or(0, D::heap_size)
+    }
+}
+
+// Where running stopped at a breakpoint or watchpoint
+#[derive(Clone, Copy, PartialEq)]
+enum Stop {
+    Breakpoint(usize), // before the op at this instr, which is run next time instead
+    Watchpoint(u64),   // after the op that made instr_count this, which run_back undoes instead
 }
 
 enum InstrResult {
     None,
-    Output(char),
     Abort(Abort),
 }
 
-impl InstrResult {
-    fn abort(message: &str) -> InstrResult {
-        InstrResult::Abort(Abort::Error(Issue::new(io::RuntimeError, message)))
+impl<D: Cell> Default for Runtime<D> {
+    fn default() -> Runtime<D> {
+        Runtime::new()
     }
 }
 
-impl<
-        D: 'static
-            + Num
-            + NumOps
-            + WrappingAdd
-            + WrappingSub
-            + ToPrimitive
-            + FromPrimitive
-            + PartialOrd
-            + Clone
-            + Copy,
-    > Runtime<D>
-{
+impl<D: Cell> Runtime<D> {
     pub fn new() -> Runtime<D> {
         Runtime {
             code: Vec::new(),
+            jumps: Vec::new(),
+            open: Vec::new(),
             stack: vec![0],
-            data: Vec::new(),
+            config: Config::new_default(),
+            tape: Tape::new(Config::new_default().tape),
             ptr: 0,
-            input_buffer: Vec::new(),
+            input: InputBuffer::new(),
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new(),
+            stopped: None,
+            instr_count: 0,
+            deadline: None,
+            loop_detector: None,
+            history: VecDeque::new(),
+            history_size: 0,
+            history_limit: 0,
         }
     }
 
-    pub fn get_ptr(&self) -> usize {
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+        self.tape.set_model(config.tape);
+        self.tape.set_limit(config.tape_limit);
+    }
+
+    pub fn get_ptr(&self) -> isize {
         self.ptr
     }
 
-    pub fn set_ptr(&mut self, ptr: usize) {
+    pub fn set_ptr(&mut self, ptr: isize) {
         self.ptr = ptr;
+        self.forget_iterations();
+    }
+
+    pub fn get_cell(&self, i: isize) -> D {
+        self.tape.get(i)
+    }
+
+    pub fn set_cell(&mut self, index: isize, value: D) {
+        self.tape.set(index, value);
+        self.forget_iterations();
+    }
+
+    // sets a cell as part of running an op
+    fn write_cell(&mut self, index: isize, value: D) {
+        if let Some(detector) = &mut self.loop_detector {
+            detector.written(index, self.tape.get(index));
+        }
+        self.tape.set(index, value);
+    }
+
+    /// The span of the op that will be run next, or None if all the code has been run
+    pub fn next_span(&self) -> Option<&Span> {
+        self.code.get(*self.stack.last().unwrap()).map(|op| &op.1)
+    }
+
+    /// How many loops the op that will be run next is in
+    pub fn loop_depth(&self) -> usize {
+        self.stack.len() - 1
+    }
+
+    /// Stops the code with Abort::Breakpoint before the op at line and col (both counting from 0)
+    /// is run
+    pub fn add_breakpoint(&mut self, line: u32, col: u32) {
+        if !self.breakpoints.contains(&(line, col)) {
+            self.breakpoints.push((line, col));
+        }
+    }
+
+    /// returns false if there was no breakpoint at line and col
+    pub fn remove_breakpoint(&mut self, line: u32, col: u32) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints
+            .retain(|breakpoint| *breakpoint != (line, col));
+        self.breakpoints.len() != len
+    }
+
+    /// If running the code would stop at the op that will be run next
+    pub fn at_breakpoint(&self) -> bool {
+        let instr = *self.stack.last().unwrap();
+        instr < self.code.len() && self.is_breakpoint(instr)
+    }
+
+    /// Stops the code with Abort::Watchpoint after an op changes the cell at index
+    pub fn add_watchpoint(&mut self, index: isize) {
+        if !self.watchpoints.contains(&index) {
+            self.watchpoints.push(index);
+        }
+    }
+
+    /// returns false if the cell was not being watched
+    pub fn remove_watchpoint(&mut self, index: isize) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watched| *watched != index);
+        self.watchpoints.len() != len
     }
 
-    pub fn get_cell(&self, i: usize) -> D {
-        if i < self.data.len() {
-            self.data[i]
+    /// Stops the code with Abort::Watchpoint after an op makes the condition true when it was not
+    /// before. It is checked after every op, starting with the next one run, so a condition that is
+    /// already true only stops the code once it has become false and true again.
+    pub fn add_condition(&mut self, condition: Condition) {
+        let met = self.is_met(&condition);
+        self.conditions.push((condition, met));
+    }
+
+    /// returns false if there was no such condition
+    pub fn remove_condition(&mut self, condition: &Condition) -> bool {
+        let len = self.conditions.len();
+        self.conditions.retain(|(c, _)| c != condition);
+        self.conditions.len() != len
+    }
+
+    /// The number of ops that have been run
+    pub fn instr_count(&self) -> u64 {
+        self.instr_count
+    }
+
+    /// Stops run with Abort::TimedOut once the deadline has passed. Time spent waiting for the Io
+    /// to give input counts, but a read that blocks is not interrupted.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Stops run with an error at a loop that will never end, which is found when an iteration of
+    /// the loop gets back to the state it started in without any input or output. It is off by
+    /// default, as it slows running down.
+    pub fn set_loop_detection(&mut self, on: bool) {
+        self.loop_detector = if on {
+            Some(LoopDetector::new(self.stack.len() - 1))
         } else {
-            D::zero()
+            None
+        };
+    }
+
+    // the loop detector can only compare iterations it has seen all of
+    fn forget_iterations(&mut self) {
+        if let Some(detector) = &mut self.loop_detector {
+            detector.forget(self.stack.len() - 1);
         }
     }
 
-    pub fn set_cell(&mut self, index: usize, value: D) {
-        if index >= self.data.len() {
-            self.data.resize(index + 1, D::zero());
+    /// Remembers what each op does, using up to about limit bytes, so run_back can undo them. The
+    /// oldest ops are forgotten once the limit is reached. The limit is 0 by default, which turns
+    /// off the history.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        self.forget_history();
+    }
+
+    // drops the oldest steps until the history is within its limit
+    fn forget_history(&mut self) {
+        while self.history_size > self.history_limit {
+            let step = self.history.pop_front().unwrap();
+            self.history_size -= step.size();
         }
-        self.data[index] = value;
     }
 
-    pub fn add_tokens(&mut self, tokens: &Vec<Token>) {
+    /// Writes the pointer, cells, loop stack, unread input and instruction count, along with a hash
+    /// of the code so the snapshot can only be loaded back with the same code. The config,
+    /// breakpoints, watchpoints, conditions and history are not saved.
+    pub fn save_snapshot<W: Write>(&self, output: W) -> ::std::io::Result<()> {
+        let (first_cell, cells) = self.tape.stored();
+        let snapshot = Snapshot {
+            code_hash: hash_code(&self.code),
+            ptr: self.ptr,
+            stack: self.stack.clone(),
+            first_cell,
+            cells,
+            input: self.input.queued().iter().cloned().collect(),
+            input_closed: self.input.is_closed(),
+            instr_count: self.instr_count,
+        };
+        snapshot.write(output)
+    }
+
+    /// Carries on from a snapshot written by save_snapshot, and clears the history. Nothing is
+    /// changed if the snapshot is not valid, or was taken while running different code.
+    pub fn load_snapshot<R: Read>(&mut self, input: R) -> Result<(), Issue> {
+        let snapshot: Snapshot<D> = Snapshot::read(input)?;
+        if snapshot.code_hash != hash_code(&self.code) {
+            return Err(Issue::new(
+                ::io::Error,
+                "Snapshot was taken while running different code",
+            ));
+        }
+        // every entry on the stack but the last is the start of a running loop
+        let (last, loops) = snapshot.stack.split_last().unwrap();
+        let fits = *last <= self.code.len()
+            && loops
+                .iter()
+                .all(|start| self.code.get(*start).map(|op| op.0) == Some(Op::Start));
+        if !fits {
+            return Err(Issue::new(
+                ::io::Error,
+                "Snapshot's loops do not match the code",
+            ));
+        }
+        if !self.tape.contains(snapshot.ptr) {
+            return Err(Issue::new(
+                ::io::Error,
+                "Snapshot's pointer is not on the tape",
+            ));
+        }
+        self.tape = Tape::new(self.config.tape);
+        self.tape.set_limit(self.config.tape_limit);
+        for (i, cell) in snapshot.cells.into_iter().enumerate() {
+            self.tape.set(snapshot.first_cell + i as isize, cell);
+        }
+        self.ptr = snapshot.ptr;
+        self.stack = snapshot.stack;
+        self.input = InputBuffer::new();
+        self.input.queue(&snapshot.input);
+        if snapshot.input_closed {
+            self.input.close();
+        }
+        self.instr_count = snapshot.instr_count;
+        self.stopped = None;
+        self.history.clear();
+        self.history_size = 0;
+        self.forget_iterations();
+        self.refresh_conditions();
+        Ok(())
+    }
+
+    fn is_breakpoint(&self, instr: usize) -> bool {
+        let (op, span) = &self.code[instr];
+        (*op == Op::Debug && self.config.debug_op)
+            || self.breakpoints.contains(&(span.line(), span.col()))
+    }
+
+    /// Adds code to the end of what is run. A loop can be opened by one call and closed by a later
+    /// one, so code can be run as it is typed.
+    pub fn add_tokens(&mut self, tokens: &[Token]) {
         let prev_end = self.code.len();
-        self.code
-            .extend(tokens.iter().filter_map(|token| match token {
-                Token::Bf(op, span) => Some((op.clone(), span.clone())),
-                _ => None,
-            }));
+        self.code.extend(ops(tokens));
+        for instr in prev_end..self.code.len() {
+            self.jumps.push(None);
+            match self.code[instr].0 {
+                Op::Start => self.open.push(instr),
+                Op::End => {
+                    if let Some(start) = self.open.pop() {
+                        self.jumps[start] = Some(instr);
+                        self.jumps[instr] = Some(start);
+                    }
+                }
+                _ => (),
+            }
+        }
         if self.stack.is_empty() && prev_end < self.code.len() {
             self.stack.push(prev_end);
         }
     }
 
+    /// Adds input for ',' to read before any more is read from the Io
+    pub fn queue_input(&mut self, input: &[u8]) {
+        self.input.queue(input);
+    }
+
     pub fn queue_input_str(&mut self, input: &str) {
-        for c in input.chars().rev() {
-            self.input_buffer.push(c);
+        self.queue_input(input.as_bytes());
+    }
+
+    /// once the queued input is used up ',' behaves as configured by Config::eof, instead of
+    /// stopping to wait for more
+    pub fn close_input(&mut self) {
+        self.input.close();
+    }
+
+    fn abort_at(&self, instr: usize, message: &str) -> InstrResult {
+        // every entry on the stack but the last is the start of a loop that is running
+        let loops = self.stack[..self.stack.len() - 1]
+            .iter()
+            .rev()
+            .map(|start| &self.code[*start].1);
+        InstrResult::Abort(error(&self.code[instr].1, message, loops))
+    }
+
+    // an error for the loop ending at instr, which will never end
+    fn never_ends(&self, instr: usize) -> InstrResult {
+        let start = self.stack[self.stack.len() - 2];
+        let span = Span::between(&self.code[start].1, &self.code[instr].1);
+        // the loop itself is the second to last entry on the stack
+        let loops = self.stack[..self.stack.len() - 2]
+            .iter()
+            .rev()
+            .map(|start| &self.code[*start].1);
+        let message = "Loop never ends, as an iteration got back to the state it started in";
+        InstrResult::Abort(error(&span, message, loops))
+    }
+
+    fn read_input<I: Io + ?Sized>(&mut self, io: &mut I) -> InputResult<D> {
+        if let Some(detector) = &mut self.loop_detector {
+            detector.io();
         }
+        self.input.read(self.config.encoding, io)
     }
 
-    fn run_instr(&mut self, instr: usize) -> InstrResult {
+    fn run_instr<I: Io + ?Sized>(&mut self, instr: usize, io: &mut I) -> InstrResult {
         let ptr = self.ptr;
         let op = self.code[instr].0;
         match op {
-            Op::Plus => {
-                let value = self.get_cell(ptr).wrapping_add(&D::one());
-                self.set_cell(ptr, value);
-                InstrResult::None
-            }
-            Op::Minus => {
-                let value = self.get_cell(ptr).wrapping_sub(&D::one());
-                self.set_cell(ptr, value);
-                InstrResult::None
-            }
-            Op::Left => {
-                if self.ptr == 0 {
-                    InstrResult::abort("Pointer moved left of the starting point")
-                } else {
-                    self.ptr -= 1;
+            Op::Plus => match self.config.overflow.add(&self.get_cell(ptr), &D::one()) {
+                Some(value) => {
+                    self.write_cell(ptr, value);
                     InstrResult::None
                 }
+                None => self.abort_at(instr, "Cell overflowed"),
+            },
+            Op::Minus => match self.config.overflow.sub(&self.get_cell(ptr), &D::one()) {
+                Some(value) => {
+                    self.write_cell(ptr, value);
+                    InstrResult::None
+                }
+                None => self.abort_at(instr, "Cell underflowed"),
+            },
+            Op::Left => match self.tape.offset(self.ptr, -1) {
+                Ok(ptr) => {
+                    self.ptr = ptr;
+                    InstrResult::None
+                }
+                Err(message) => self.abort_at(instr, message),
+            },
+            Op::Right => match self.tape.offset(self.ptr, 1) {
+                Ok(ptr) => {
+                    self.ptr = ptr;
+                    InstrResult::None
+                }
+                Err(message) => self.abort_at(instr, message),
+            },
+            Op::Output => {
+                if let Some(detector) = &mut self.loop_detector {
+                    detector.io();
+                }
+                let mut buffer = [0; 4];
+                let value = self.get_cell(ptr);
+                match io.write(self.config.encoding.write(&value, &mut buffer)) {
+                    Ok(()) => InstrResult::None,
+                    Err(_) => self.abort_at(instr, "Failed to write output"),
+                }
             }
-            Op::Right => {
-                self.ptr += 1;
-                InstrResult::None
-            }
-            Op::Output => InstrResult::Output(
-                char::from_u32(self.get_cell(self.ptr).to_u32().unwrap()).unwrap_or('\0'),
-            ),
-            Op::Input => match self.input_buffer.pop() {
-                Some(c) => {
-                    let value = D::from_u8(c as u8).unwrap();
-                    self.set_cell(ptr, value);
+            Op::Input => match self.read_input(io) {
+                InputResult::Value(value) => {
+                    self.write_cell(ptr, value);
                     InstrResult::None
                 }
-                None => InstrResult::Abort(Abort::AwaitingInput),
+                InputResult::Waiting => InstrResult::Abort(Abort::AwaitingInput),
+                InputResult::Ended => match self.config.eof.apply(self.get_cell(ptr)) {
+                    Some(value) => {
+                        self.write_cell(ptr, value);
+                        InstrResult::None
+                    }
+                    None => self.abort_at(instr, "Tried to read past the end of input"),
+                },
+                InputResult::Error(message) => self.abort_at(instr, message),
             },
             Op::Start => {
+                if let Some(detector) = &mut self.loop_detector {
+                    detector.tested(ptr);
+                }
                 if self.get_cell(self.ptr) == D::zero() {
-                    let mut instr = instr + 1;
-                    let mut level = 1;
-                    loop {
-                        if instr >= self.code.len() {
-                            break InstrResult::Abort(Abort::Completed);
-                        }
-                        match self.code[instr].0 {
-                            Op::Start => level += 1,
-                            Op::End => level -= 1,
-                            _ => (),
-                        }
-                        if level <= 0 {
+                    match self.jumps[instr] {
+                        Some(end) => {
                             let last_index = self.stack.len() - 1;
-                            self.stack[last_index] = instr;
-                            break InstrResult::None;
+                            self.stack[last_index] = end;
+                            InstrResult::None
                         }
-                        instr += 1;
+                        // the matching ']' has not been added yet
+                        None => InstrResult::Abort(Abort::Completed),
                     }
                 } else {
+                    if let Some(detector) = &mut self.loop_detector {
+                        detector.enter(ptr);
+                    }
                     self.stack.push(instr);
                     InstrResult::None
                 }
             }
             Op::End => {
                 if self.stack.len() <= 1 {
-                    InstrResult::abort("Extraneous closing brace")
+                    self.abort_at(instr, "Extraneous closing brace")
                 } else {
-                    if self.get_cell(self.ptr) == D::zero() {
+                    let zero = self.get_cell(self.ptr) == D::zero();
+                    if let Some(detector) = &mut self.loop_detector {
+                        detector.tested(ptr);
+                        if zero {
+                            detector.exit();
+                        } else {
+                            // an error could stop the loop if a cell it doesn't test can overflow
+                            let trap = self.config.overflow == Overflow::Trap;
+                            let tape = &self.tape;
+                            if detector.repeats(ptr, trap, |index| tape.get(index)) {
+                                return self.never_ends(instr);
+                            }
+                        }
+                    }
+                    if zero {
                         self.stack.pop().unwrap();
                         let last_index = self.stack.len() - 1;
                         self.stack[last_index] = instr;
@@ -171,31 +479,213 @@ impl<
                     InstrResult::None
                 }
             }
+            Op::Debug => {
+                // stopping to show the tape counts as output
+                if let Some(detector) = &mut self.loop_detector {
+                    detector.io();
+                }
+                InstrResult::None
+            }
+        }
+    }
+
+    fn is_met(&self, condition: &Condition) -> bool {
+        condition.is_met(self.ptr, self.instr_count, |index| {
+            self.tape.get(index).to_bigint()
+        })
+    }
+
+    // after the state changes other than by running an op, conditions that are met now only stop
+    // running forwards once they become false and true again
+    fn refresh_conditions(&mut self) {
+        for i in 0..self.conditions.len() {
+            self.conditions[i].1 = self.is_met(&self.conditions[i].0);
         }
     }
 
-    pub fn run<F>(&mut self, mut instr_cap: Option<usize>, handle_output: &mut F) -> Abort
-    where
-        F: FnMut(char),
-    {
+    // returns true if any condition has just become true
+    fn conditions_met(&mut self) -> bool {
+        let mut newly_met = false;
+        for i in 0..self.conditions.len() {
+            let met = self.is_met(&self.conditions[i].0);
+            newly_met |= met && !self.conditions[i].1;
+            self.conditions[i].1 = met;
+        }
+        newly_met
+    }
+
+    /// Runs code until it ends, instr_cap instructions have been run or it can't go on. Output is
+    /// written to io, and input is read from it once the queued input is used up.
+    pub fn run<I: Io + ?Sized>(&mut self, instr_cap: Option<usize>, io: &mut I) -> Abort {
+        self.run_with(instr_cap, io, &mut ())
+    }
+
+    /// Runs code like run, telling observer about each op that is run
+    pub fn run_with<I: Io + ?Sized, O: Observer<D> + ?Sized>(
+        &mut self,
+        mut instr_cap: Option<usize>,
+        io: &mut I,
+        observer: &mut O,
+    ) -> Abort {
+        if instr_cap == Some(0) {
+            return Abort::InstrCapped;
+        }
+        // the first op may be the breakpoint the last run stopped at
+        let mut resume = self.stopped.take();
         loop {
             let instr = *self.stack.last().unwrap();
             if instr >= self.code.len() {
                 break Abort::Completed;
             }
-            match self.run_instr(instr) {
+            if resume.take() != Some(Stop::Breakpoint(instr)) && self.is_breakpoint(instr) {
+                self.stopped = Some(Stop::Breakpoint(instr));
+                break Abort::Breakpoint(self.code[instr].1.clone());
+            }
+            let op = self.code[instr].0;
+            if op == Op::Debug && !self.config.debug_op {
+                // a '#' is only a comment, so it isn't counted as an op that was run
+                let last_index = self.stack.len() - 1;
+                self.stack[last_index] += 1;
+                continue;
+            }
+            let changes_cell = matches!(op, Op::Plus | Op::Minus | Op::Input);
+            let watched = changes_cell && self.watchpoints.contains(&self.ptr);
+            let recording = self.history_limit > 0;
+            let mut step = Step {
+                instr,
+                stack_len: self.stack.len(),
+                popped: match op {
+                    Op::End if self.stack.len() > 1 => Some(self.stack[self.stack.len() - 2]),
+                    _ => None,
+                },
+                ptr: self.ptr,
+                cell: if changes_cell {
+                    Some(self.get_cell(self.ptr))
+                } else {
+                    None
+                },
+                input: Vec::new(),
+            };
+            match self.run_instr(instr, io) {
                 InstrResult::None => (),
-                InstrResult::Output(c) => handle_output(c),
                 InstrResult::Abort(a) => break a,
             }
+            let after = self.get_cell(step.ptr);
+            observer.op_run(&Event {
+                count: self.instr_count,
+                instr,
+                op,
+                span: &self.code[instr].1,
+                ptr: step.ptr,
+                before: step.cell.as_ref().unwrap_or(&after),
+                after: &after,
+            });
+            self.instr_count += 1;
+            let changed = watched && step.cell.as_ref() != Some(&after);
+            let met = self.conditions_met();
             let last_index = self.stack.len() - 1;
             self.stack[last_index] += 1;
+            if recording {
+                if op == Op::Input {
+                    step.input = self.input.taken().to_vec();
+                }
+                self.history_size += step.size();
+                self.history.push_back(step);
+                self.forget_history();
+            }
+            if changed || met {
+                self.stopped = Some(Stop::Watchpoint(self.instr_count));
+                break Abort::Watchpoint(self.code[instr].1.clone());
+            }
             if let Some(instr_cap) = &mut instr_cap {
                 *instr_cap -= 1;
-                if *instr_cap <= 0 {
+                if *instr_cap == 0 {
                     break Abort::InstrCapped;
                 }
             }
+            if past_deadline(self.deadline, self.instr_count) {
+                break Abort::TimedOut;
+            }
         }
     }
+
+    fn undo(&mut self, step: Step<D>) {
+        if self.stack.len() < step.stack_len {
+            // the op ended a loop
+            let last_index = self.stack.len() - 1;
+            self.stack[last_index] = step.popped.unwrap();
+            self.stack.push(step.instr);
+        } else {
+            // the op may have started a loop
+            self.stack.truncate(step.stack_len);
+            let last_index = self.stack.len() - 1;
+            self.stack[last_index] = step.instr;
+        }
+        self.ptr = step.ptr;
+        if let Some(value) = step.cell {
+            self.set_cell(step.ptr, value);
+        }
+        self.input.unread(&step.input);
+        self.instr_count -= 1;
+    }
+
+    // if running step, the most recent op, changed a watched cell or made a condition true
+    fn step_stops(&self, step: &Step<D>) -> bool {
+        let changed = match &step.cell {
+            Some(value) => {
+                self.watchpoints.contains(&step.ptr) && *value != self.get_cell(step.ptr)
+            }
+            None => false,
+        };
+        let cell_before = |index| match &step.cell {
+            Some(value) if index == step.ptr => value.to_bigint(),
+            _ => self.tape.get(index).to_bigint(),
+        };
+        changed
+            || self.conditions.iter().any(|(condition, _)| {
+                self.is_met(condition)
+                    && !condition.is_met(step.ptr, self.instr_count - 1, cell_before)
+            })
+    }
+
+    /// Undoes ops, the most recently run first, until instr_cap ops have been undone, there are no
+    /// more in the history, or it gets back to where running forwards would have stopped at a
+    /// breakpoint or watchpoint. Output that has been written is not taken back, so it is written
+    /// again when the code is run forwards again.
+    pub fn run_back(&mut self, mut instr_cap: Option<usize>) -> Abort {
+        if instr_cap == Some(0) {
+            return Abort::InstrCapped;
+        }
+        // the first op undone may be the one a watchpoint stopped the last run after
+        let mut resume = self.stopped.take();
+        let abort = loop {
+            let count = self.instr_count;
+            let step = match self.history.pop_back() {
+                Some(step) => step,
+                None => break Abort::StartOfHistory,
+            };
+            if resume.take() != Some(Stop::Watchpoint(count)) && self.step_stops(&step) {
+                let span = self.code[step.instr].1.clone();
+                self.history.push_back(step);
+                self.stopped = Some(Stop::Watchpoint(count));
+                break Abort::Watchpoint(span);
+            }
+            self.history_size -= step.size();
+            self.undo(step);
+            let instr = *self.stack.last().unwrap();
+            if self.is_breakpoint(instr) {
+                self.stopped = Some(Stop::Breakpoint(instr));
+                break Abort::Breakpoint(self.code[instr].1.clone());
+            }
+            if let Some(instr_cap) = &mut instr_cap {
+                *instr_cap -= 1;
+                if *instr_cap == 0 {
+                    break Abort::InstrCapped;
+                }
+            }
+        };
+        self.forget_iterations();
+        self.refresh_conditions();
+        abort
+    }
 }
@@ -0,0 +1,95 @@
+use super::*;
+use std::collections::VecDeque;
+use std::str;
+
+// Output in place of code points that are not valid chars, and input that is not valid UTF-8
+const REPLACEMENT: char = '\u{fffd}';
+
+/// How ',' turns input bytes into a cell value and '.' turns a cell value into output bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Bytes, // each value is a single byte
+    Utf8,  // each value is a unicode code point, made up of 1 to 4 bytes
+}
+
+pub enum InputResult<D> {
+    Value(D),
+    Waiting,             // the next value is not all in the input yet
+    Ended,               // input is closed and has all been read
+    Error(&'static str), // the value can't be stored in a cell
+}
+
+// the number of bytes in a UTF-8 sequence that starts with byte, or None if none can
+fn utf8_len(byte: u8) -> Option<usize> {
+    match byte {
+        0x00..=0x7f => Some(1),
+        0xc0..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf7 => Some(4),
+        _ => None,
+    }
+}
+
+// Takes the next char from the front of input. Invalid bytes are taken one at a time and each
+// read as the replacement char.
+fn read_char(input: &mut VecDeque<u8>, closed: bool) -> Option<char> {
+    let len = utf8_len(input[0]).unwrap_or(1);
+    let continued = input
+        .iter()
+        .skip(1)
+        .take(len - 1)
+        .all(|byte| byte & 0xc0 == 0x80);
+    if continued && input.len() < len && !closed {
+        return None;
+    }
+    let bytes: Vec<u8> = input.iter().take(len).cloned().collect();
+    match str::from_utf8(&bytes) {
+        Ok(s) => {
+            input.drain(..len);
+            s.chars().next()
+        }
+        Err(_) => {
+            input.pop_front();
+            Some(REPLACEMENT)
+        }
+    }
+}
+
+impl Encoding {
+    // takes the next value for ',' from the front of input
+    pub(crate) fn read<D: Cell>(self, input: &mut VecDeque<u8>, closed: bool) -> InputResult<D> {
+        if input.is_empty() {
+            return if closed {
+                InputResult::Ended
+            } else {
+                InputResult::Waiting
+            };
+        }
+        match self {
+            Encoding::Bytes => InputResult::Value(D::from_byte(input.pop_front().unwrap())),
+            Encoding::Utf8 => match read_char(input, closed) {
+                Some(c) => match D::from_code_point(c as u32) {
+                    Some(value) => InputResult::Value(value),
+                    None => InputResult::Error("Input character is too large for a cell"),
+                },
+                None => InputResult::Waiting,
+            },
+        }
+    }
+
+    // the bytes '.' outputs for value, which are stored in buffer
+    pub(crate) fn write<'a, D: Cell>(self, value: &D, buffer: &'a mut [u8; 4]) -> &'a [u8] {
+        match self {
+            Encoding::Bytes => {
+                buffer[0] = value.to_byte();
+                &buffer[..1]
+            }
+            Encoding::Utf8 => value
+                .to_code_point()
+                .and_then(char::from_u32)
+                .unwrap_or(REPLACEMENT)
+                .encode_utf8(buffer)
+                .as_bytes(),
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use super::encoding::InputResult;
+use super::ir::{Instr, Node};
+use super::stream::InputBuffer;
+use super::*;
+use source::Span;
+use source::Token;
+use std::time::Instant;
+
+/// Runs code lowered to the optimized IR. Only the pointer position and cells at the end of each
+/// straight-line section of code (between loop brackets) match what debug::Runtime would have.
+pub struct Runtime<D> {
+    ops: Vec<(Op, Span)>,
+    code: Vec<Node>,
+    pc: usize,
+    config: Config,
+    tape: Tape<D>,
+    ptr: isize,
+    input: InputBuffer,
+    instr_count: u64,
+    deadline: Option<Instant>,
+    stopped: Option<usize>, // the '#' the last run stopped at, which is run next time instead
+}
+
+enum InstrResult {
+    None,
+    Jump(usize),
+    Abort(Abort),
+}
+
+fn overflow_message(op: Op) -> &'static str {
+    match op {
+        Op::Plus => "Cell overflowed",
+        _ => "Cell underflowed",
+    }
+}
+
+impl<D: Cell> Runtime<D> {
+    pub fn new(tokens: &[Token]) -> Runtime<D> {
+        let ops = ops(tokens);
+        let config = Config::new_default();
+        Runtime {
+            code: ir::lower(&ops, &config),
+            ops,
+            pc: 0,
+            config,
+            tape: Tape::new(config.tape),
+            ptr: 0,
+            input: InputBuffer::new(),
+            instr_count: 0,
+            deadline: None,
+            stopped: None,
+        }
+    }
+
+    /// should be called before the code is run, as it may need to be lowered again
+    pub fn set_config(&mut self, config: Config) {
+        if config != self.config {
+            self.code = ir::lower(&self.ops, &config);
+            self.stopped = None;
+        }
+        self.config = config;
+        self.tape.set_model(config.tape);
+        self.tape.set_limit(config.tape_limit);
+    }
+
+    pub fn get_ptr(&self) -> isize {
+        self.ptr
+    }
+
+    pub fn set_ptr(&mut self, ptr: isize) {
+        self.ptr = ptr;
+    }
+
+    pub fn get_cell(&self, i: isize) -> D {
+        self.tape.get(i)
+    }
+
+    pub fn set_cell(&mut self, index: isize, value: D) {
+        self.tape.set(index, value);
+    }
+
+    /// The number of ops that have been run, the same as debug::Runtime would count. Every op that
+    /// was lowered into an instruction counts, including each time around a loop it stands for.
+    pub fn instr_count(&self) -> u64 {
+        self.instr_count
+    }
+
+    /// Stops run with Abort::TimedOut once the deadline has passed. Time spent waiting for the Io
+    /// to give input counts, but a read that blocks is not interrupted.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Adds input for ',' to read before any more is read from the Io
+    pub fn queue_input(&mut self, input: &[u8]) {
+        self.input.queue(input);
+    }
+
+    pub fn queue_input_str(&mut self, input: &str) {
+        self.queue_input(input.as_bytes());
+    }
+
+    /// once the queued input is used up ',' behaves as configured by Config::eof, instead of
+    /// stopping to wait for more
+    pub fn close_input(&mut self) {
+        self.input.close();
+    }
+
+    fn abort(&self, message: &str) -> InstrResult {
+        self.abort_at(&self.code[self.pc].span(), message)
+    }
+
+    fn abort_at(&self, span: &Span, message: &str) -> InstrResult {
+        // loops are always entered and left in the order they are written, so the ones that are
+        // running are the ones that were opened before span and not closed yet
+        let mut loops = Vec::new();
+        for (op, op_span) in self.ops.iter() {
+            if op_span.start_byte() >= span.start_byte() {
+                break;
+            }
+            match op {
+                Op::Start => loops.push(op_span),
+                Op::End => {
+                    loops.pop();
+                }
+                _ => (),
+            }
+        }
+        InstrResult::Abort(error(span, message, loops.into_iter().rev()))
+    }
+
+    // failing to get to the offset a node uses is reported at the first move on the way that fails
+    fn abort_moved(&self, message: &str) -> InstrResult {
+        for (offset, span) in &self.code[self.pc].moved {
+            if let Err(message) = self.tape.offset(self.ptr, *offset) {
+                return self.abort_at(span, message);
+            }
+        }
+        self.abort(message)
+    }
+
+    // Mul and Scan nodes stand for a whole loop, so when one can't get to a cell, the moves in
+    // the loop are run one at a time from ptr to find the one that fails, as in debug::Runtime
+    fn abort_in_loop(&self, ptr: isize, message: &str) -> InstrResult {
+        let mut moves = Vec::new();
+        for span in &self.code[self.pc].spans {
+            let start = span.start_byte();
+            if let Ok(i) = self
+                .ops
+                .binary_search_by_key(&start, |(_, s)| s.start_byte())
+            {
+                match self.ops[i].0 {
+                    Op::Left => moves.push((-1, span)),
+                    Op::Right => moves.push((1, span)),
+                    _ => (),
+                }
+            }
+        }
+        let mut ptr = ptr;
+        while !moves.is_empty() && self.get_cell(ptr) != D::zero() {
+            for (by, span) in &moves {
+                match self.tape.offset(ptr, *by) {
+                    Ok(next) => ptr = next,
+                    Err(message) => return self.abort_at(span, message),
+                }
+            }
+        }
+        self.abort(message)
+    }
+
+    fn change(&mut self, address: isize, amount: u8, op: Op) -> InstrResult {
+        let overflow = self.config.overflow;
+        let step = |value: &D, amount: &D| match op {
+            Op::Plus => overflow.add(value, amount),
+            _ => overflow.sub(value, amount),
+        };
+        let value = self.get_cell(address);
+        match step(&value, &D::from_byte(amount)) {
+            Some(value) => {
+                self.set_cell(address, value);
+                InstrResult::None
+            }
+            None => {
+                // find which of the ops that were combined into this node went out of range
+                let mut value = value;
+                let mut ops = self.code[self.pc].spans.iter();
+                let mut span = ops.next().unwrap();
+                while let Some(next) = step(&value, &D::one()) {
+                    value = next;
+                    span = ops.next().unwrap();
+                }
+                let span = span.clone();
+                self.abort_at(&span, overflow_message(op))
+            }
+        }
+    }
+
+    // counts the ops run going around the loop the node stands for, which are all but its '['
+    fn count_iterations(&mut self, iterations: u64) {
+        let ops = self.code[self.pc].spans.len() as u64 - 1;
+        self.instr_count = self
+            .instr_count
+            .saturating_add(iterations.saturating_mul(ops));
+    }
+
+    // adds or subtracts the value of one cell to another factor times
+    fn multiply(&mut self, value: D, to: isize, factor: i8) -> InstrResult {
+        let overflow = self.config.overflow;
+        // The loop cell only gets from below zero back to zero by wrapping around. Otherwise it
+        // stays at its limit or keeps going down forever, so spinning here has the same result as
+        // running the loop.
+        if value < D::zero() && !(D::BOUNDED && overflow == Overflow::Wrap) {
+            self.count_iterations(1);
+            return InstrResult::Jump(self.pc);
+        }
+        let target = self.get_cell(to);
+        let amount = D::from_byte(factor.unsigned_abs());
+        let result = match overflow {
+            Overflow::Wrap if factor < 0 => target.wrapping_sub(&value.wrapping_mul(&amount)),
+            Overflow::Wrap => target.wrapping_add(&value.wrapping_mul(&amount)),
+            overflow => {
+                let mut result = target;
+                for _ in 0..factor.unsigned_abs() {
+                    let next = if factor < 0 {
+                        overflow.sub(&result, &value)
+                    } else {
+                        overflow.add(&result, &value)
+                    };
+                    result = next.unwrap();
+                }
+                result
+            }
+        };
+        self.set_cell(to, result);
+        InstrResult::None
+    }
+
+    fn scan(&mut self, stride: isize) -> InstrResult {
+        match self.tape.scan(self.ptr, stride) {
+            Ok(Some((ptr, moves))) => {
+                self.ptr = ptr;
+                self.count_iterations(moves as u64);
+                InstrResult::None
+            }
+            // the code would loop forever, so keep running this instruction
+            Ok(None) => {
+                self.count_iterations(1);
+                InstrResult::Jump(self.pc)
+            }
+            Err(message) => self.abort_in_loop(self.ptr, message),
+        }
+    }
+
+    fn run_instr<I: Io + ?Sized>(&mut self, io: &mut I) -> InstrResult {
+        let instr = self.code[self.pc].instr;
+        let address = match instr {
+            Instr::Add(offset, _)
+            | Instr::Sub(offset, _)
+            | Instr::Clear(offset, _)
+            | Instr::Mul(offset, _, _)
+            | Instr::Check(offset)
+            | Instr::Move(offset)
+            | Instr::Output(offset)
+            | Instr::Input(offset) => match self.tape.offset(self.ptr, offset) {
+                Ok(address) => address,
+                Err(message) => return self.abort_moved(message),
+            },
+            _ => self.ptr,
+        };
+        match instr {
+            Instr::Add(_, amount) => self.change(address, amount, Op::Plus),
+            Instr::Sub(_, amount) => self.change(address, amount, Op::Minus),
+            Instr::Clear(_, op) => {
+                let value = self.get_cell(address);
+                // the cell would have to go past the end of its range to get to zero
+                let past_end = match op {
+                    Op::Plus => value > D::zero(),
+                    _ => value < D::zero(),
+                };
+                let overflow = self.config.overflow;
+                match overflow {
+                    // an unbounded cell never gets back around to zero, and a saturating one gets
+                    // stuck at the end of its range
+                    _ if past_end && (!D::BOUNDED || overflow == Overflow::Saturate) => {
+                        self.count_iterations(1);
+                        InstrResult::Jump(self.pc)
+                    }
+                    Overflow::Trap if past_end => {
+                        let span = self.code[self.pc].spans[1].clone();
+                        self.abort_at(&span, overflow_message(op))
+                    }
+                    _ => {
+                        let iterations = match op {
+                            Op::Plus => D::zero().wrapping_sub(&value).decrements_to_zero(),
+                            _ => value.decrements_to_zero(),
+                        };
+                        self.count_iterations(iterations);
+                        self.set_cell(address, D::zero());
+                        InstrResult::None
+                    }
+                }
+            }
+            Instr::Mul(_, to, factor) => {
+                let value = self.get_cell(address);
+                if value == D::zero() {
+                    return InstrResult::None;
+                }
+                match self.tape.offset(self.ptr, to) {
+                    Ok(to) => self.multiply(value, to, factor),
+                    Err(message) => self.abort_in_loop(address, message),
+                }
+            }
+            Instr::Scan(stride) => self.scan(stride),
+            Instr::Check(_) => InstrResult::None,
+            Instr::Move(_) => {
+                self.ptr = address;
+                InstrResult::None
+            }
+            Instr::Output(_) => {
+                let mut buffer = [0; 4];
+                let value = self.get_cell(address);
+                match io.write(self.config.encoding.write(&value, &mut buffer)) {
+                    Ok(()) => InstrResult::None,
+                    Err(_) => self.abort("Failed to write output"),
+                }
+            }
+            Instr::Input(_) => match self.input.read(self.config.encoding, io) {
+                InputResult::Value(value) => {
+                    self.set_cell(address, value);
+                    InstrResult::None
+                }
+                InputResult::Waiting => InstrResult::Abort(Abort::AwaitingInput),
+                InputResult::Ended => match self.config.eof.apply(self.get_cell(address)) {
+                    Some(value) => {
+                        self.set_cell(address, value);
+                        InstrResult::None
+                    }
+                    None => self.abort("Tried to read past the end of input"),
+                },
+                InputResult::Error(message) => self.abort(message),
+            },
+            Instr::Open(target) => {
+                if self.get_cell(address) == D::zero() {
+                    InstrResult::Jump(target)
+                } else {
+                    InstrResult::None
+                }
+            }
+            Instr::Close(target) => {
+                if self.get_cell(address) != D::zero() {
+                    InstrResult::Jump(target)
+                } else {
+                    InstrResult::None
+                }
+            }
+            Instr::Debug => InstrResult::None,
+            Instr::Fault(message) => self.abort(message),
+        }
+    }
+
+    /// Runs code until it ends, at least instr_cap ops have been run or it can't go on. Ops that
+    /// were lowered into one instruction are all run together, so a few more than instr_cap can be.
+    /// Output is written to io, and input is read from it once the queued input is used up.
+    pub fn run<I: Io + ?Sized>(&mut self, instr_cap: Option<usize>, io: &mut I) -> Abort {
+        if instr_cap == Some(0) {
+            return Abort::InstrCapped;
+        }
+        let end = instr_cap.map(|cap| self.instr_count.saturating_add(cap as u64));
+        // the time is looked at by the number of instructions run, as ops are counted unevenly
+        let mut instrs_run: u64 = 0;
+        // the first node may be the '#' the last run stopped at
+        let mut resume = self.stopped.take();
+        loop {
+            if self.pc >= self.code.len() {
+                break Abort::Completed;
+            }
+            if resume.take() != Some(self.pc) && self.code[self.pc].instr == Instr::Debug {
+                self.stopped = Some(self.pc);
+                break Abort::Breakpoint(self.code[self.pc].span());
+            }
+            let ops = self.code[self.pc].ops as u64;
+            match self.run_instr(io) {
+                InstrResult::None => self.pc += 1,
+                InstrResult::Jump(target) => self.pc = target,
+                InstrResult::Abort(a) => break a,
+            }
+            self.instr_count = self.instr_count.saturating_add(ops);
+            if end.is_some_and(|end| self.instr_count >= end) {
+                break Abort::InstrCapped;
+            }
+            instrs_run += 1;
+            if past_deadline(self.deadline, instrs_run) {
+                break Abort::TimedOut;
+            }
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use std::cmp;
+use std::mem;
+
+use super::*;
+use source::Span;
+
+// Longest run of '+' or '-' collapsed into a single node, small enough to fit in any cell type
+const MAX_RUN: u8 = 127;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Add(isize, u8),        // add to the cell at an offset from the pointer
+    Sub(isize, u8),        // subtract from the cell at an offset from the pointer
+    Clear(isize, Op),      // '[-]' or '[+]' on the cell at an offset from the pointer
+    Mul(isize, isize, i8), // if the first cell is not zero, add it times a factor to the second
+    Scan(isize),           // move the pointer by a stride until it is on a zero cell
+    Check(isize), // the pointer passed over an offset without accessing it, make sure it was allowed to
+    Move(isize),  // apply the offset accumulated since the last move to the pointer
+    Output(isize),
+    Input(isize),
+    Open(usize),  // jump to the given node if the current cell is zero
+    Close(usize), // jump to the given node if the current cell is not zero
+    Debug,        // a '#', the pointer and cells must be as they would be in debug::Runtime
+    Fault(&'static str),
+}
+
+// '<'s and '>'s that reached a new lowest or highest offset, each with the offset it reached
+pub type Moves = Vec<(isize, Span)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub instr: Instr,
+    pub spans: Vec<Span>, // the source of every op that was lowered into this node
+    // The moves since the pointer was last checked. If this node can't get to its offset, the
+    // first of them that couldn't is where it failed.
+    pub moved: Moves,
+    // How many ops running the node counts as, not counting the iterations of a loop it stands for
+    pub ops: usize,
+}
+
+impl Node {
+    fn new(instr: Instr, span: &Span) -> Node {
+        Node {
+            instr,
+            spans: vec![span.clone()],
+            moved: Vec::new(),
+            ops: 1,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::between(&self.spans[0], &self.spans[self.spans.len() - 1])
+    }
+}
+
+// Code with its loops nested, so they can be matched against known patterns
+enum Block<'a> {
+    Op(Op, &'a Span),
+    Loop {
+        body: Vec<Block<'a>>,
+        start: &'a Span,
+        end: Option<&'a Span>, // None if the loop is never closed
+    },
+    Extraneous(&'a Span), // a ']' without a matching '['
+}
+
+impl<'a> Block<'a> {
+    fn spans(&self, spans: &mut Vec<Span>) {
+        match self {
+            Block::Op(_, span) | Block::Extraneous(span) => spans.push((*span).clone()),
+            Block::Loop { body, start, end } => {
+                spans.push((*start).clone());
+                for block in body {
+                    block.spans(spans);
+                }
+                if let Some(end) = end {
+                    spans.push((*end).clone());
+                }
+            }
+        }
+    }
+
+    fn op(&self) -> Option<Op> {
+        match self {
+            Block::Op(op, _) => Some(*op),
+            _ => None,
+        }
+    }
+}
+
+fn nest(code: &[(Op, Span)]) -> Vec<Block<'_>> {
+    let mut outer: Vec<(Vec<Block>, &Span)> = Vec::new();
+    let mut blocks = Vec::new();
+    for (op, span) in code {
+        match op {
+            Op::Start => {
+                outer.push((blocks, span));
+                blocks = Vec::new();
+            }
+            Op::End => match outer.pop() {
+                Some((mut parent, start)) => {
+                    parent.push(Block::Loop {
+                        body: blocks,
+                        start,
+                        end: Some(span),
+                    });
+                    blocks = parent;
+                }
+                None => blocks.push(Block::Extraneous(span)),
+            },
+            _ => blocks.push(Block::Op(*op, span)),
+        }
+    }
+    while let Some((mut parent, start)) = outer.pop() {
+        parent.push(Block::Loop {
+            body: blocks,
+            start,
+            end: None,
+        });
+        blocks = parent;
+    }
+    blocks
+}
+
+// If a loop body only moves the pointer in one direction, returns how far it moves
+fn scan_stride(body: &[Block]) -> Option<isize> {
+    let step = match body.first()?.op()? {
+        Op::Left => -1,
+        Op::Right => 1,
+        _ => return None,
+    };
+    if body.iter().all(|block| block.op() == body[0].op()) {
+        Some(step * body.len() as isize)
+    } else {
+        None
+    }
+}
+
+// If a loop body only moves the pointer and changes cells, ends where it started and subtracts one
+// from the loop cell, every iteration adds the same amount to each other cell it touches. Returns
+// the offset of each of those cells and how much gets added to it per iteration. The lowest and
+// highest offsets come first so moving outside of the tape is caught before anything changes.
+// Unless overflow wraps, a cell that goes both up and down can get stuck at its limit part way
+// through an iteration, so those loops are left to run op by op.
+fn multiply_targets(body: &[Block], overflow: Overflow) -> Option<Vec<(isize, i8)>> {
+    let mut offset: isize = 0;
+    let mut targets: Vec<(isize, isize)> = Vec::new();
+    // the offsets of the cells that are added to and the ones that are subtracted from
+    let mut added = Vec::new();
+    let mut subtracted = Vec::new();
+    for block in body {
+        let change = match block.op()? {
+            Op::Plus => 1,
+            Op::Minus => -1,
+            Op::Left => {
+                offset -= 1;
+                0
+            }
+            Op::Right => {
+                offset += 1;
+                0
+            }
+            _ => return None,
+        };
+        match change {
+            1 => added.push(offset),
+            -1 => subtracted.push(offset),
+            _ => (),
+        }
+        match targets.iter_mut().find(|(o, _)| *o == offset) {
+            Some((_, factor)) => *factor += change,
+            None => targets.push((offset, change)),
+        }
+    }
+    if offset != 0 {
+        return None;
+    }
+    if overflow != Overflow::Wrap && added.iter().any(|o| subtracted.contains(o)) {
+        return None;
+    }
+    let (loop_cell, others): (Vec<_>, Vec<_>) = targets.into_iter().partition(|(o, _)| *o == 0);
+    if loop_cell != vec![(0, -1)] {
+        return None;
+    }
+    let low = others.iter().map(|(o, _)| *o).min();
+    let high = others.iter().map(|(o, _)| *o).max();
+    let mut targets = Vec::new();
+    for (o, factor) in others {
+        if factor.abs() > MAX_RUN as isize {
+            return None;
+        }
+        // cells that are only passed over don't need a node, unless they need to be checked
+        if factor != 0 || Some(o) == low || Some(o) == high {
+            targets.push((o, factor as i8));
+        }
+    }
+    targets.sort_by_key(|(o, _)| match (Some(*o) == low, Some(*o) == high) {
+        (true, _) => 0,
+        (_, true) => 1,
+        _ => 2,
+    });
+    Some(targets)
+}
+
+struct Lowering {
+    overflow: Overflow,
+    tape: TapeModel,
+    check_every_move: bool,
+    nodes: Vec<Node>,
+    unclosed: Vec<usize>, // Open nodes that jump to the end of the program
+    offset: isize,        // pointer offset accumulated since the last Move
+    low: isize,           // lowest offset reached since the last Move
+    high: isize,          // highest offset reached since the last Move
+    unchecked: Moves,     // moves to a new low or high that have not been checked yet
+    moves: Vec<Span>,
+}
+
+impl Lowering {
+    fn new(config: &Config) -> Lowering {
+        Lowering {
+            overflow: config.overflow,
+            tape: config.tape,
+            // Once a wrapping tape is past its size limit, moving further can get back inside it,
+            // and on a tape that is infinite both ways, writing to the cells on one side leaves
+            // less room on the other. Either way getting to the furthest cell doesn't mean every
+            // move on the way there was allowed.
+            check_every_move: match config.tape {
+                TapeModel::Wrapping(len) => config.tape_limit.is_some_and(|limit| limit < len),
+                TapeModel::Infinite => config.tape_limit.is_some(),
+                _ => false,
+            },
+            nodes: Vec::new(),
+            unclosed: Vec::new(),
+            offset: 0,
+            low: 0,
+            high: 0,
+            unchecked: Vec::new(),
+            moves: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    // Accessing the cell at the current offset checks it as well as a Check would. Returns the
+    // offset, and the moves on the way to it that had not been checked yet.
+    fn access(&mut self) -> (isize, Moves) {
+        (self.offset, mem::take(&mut self.unchecked))
+    }
+
+    fn step(&mut self, by: isize, span: &Span) {
+        self.offset += by;
+        if self.check_every_move {
+            self.unchecked.push((self.offset, span.clone()));
+            let (offset, moved) = self.access();
+            self.push_check(offset, span, moved);
+        } else if self.offset < self.low || self.offset > self.high {
+            // checking the new low or high also covers the old one
+            self.low = cmp::min(self.low, self.offset);
+            self.high = cmp::max(self.high, self.offset);
+            self.unchecked.push((self.offset, span.clone()));
+        } else if let Some((offset, last)) = self.unchecked.last().cloned() {
+            let (_, moved) = self.access();
+            self.push_check(offset, &last, moved);
+        }
+        self.moves.push(span.clone());
+    }
+
+    // the moves a Check is for are counted as ops by the Move after them
+    fn push_check(&mut self, offset: isize, span: &Span, moved: Moves) {
+        let mut node = Node::new(Instr::Check(offset), span);
+        node.moved = moved;
+        node.ops = 0;
+        self.push(node);
+    }
+
+    fn flush_moves(&mut self) {
+        if self.offset != 0 {
+            let node = Node {
+                instr: Instr::Move(self.offset),
+                spans: self.moves.clone(),
+                moved: mem::take(&mut self.unchecked),
+                ops: self.moves.len(),
+            };
+            self.push(node);
+        } else if !self.moves.is_empty() {
+            // Moves that get back to where they started don't need a Move, but the first of them
+            // always needs checking, so there is a node after it to count them
+            self.nodes.last_mut().unwrap().ops += self.moves.len();
+        }
+        self.offset = 0;
+        self.low = 0;
+        self.high = 0;
+        self.unchecked.clear();
+        self.moves.clear();
+    }
+
+    fn add(&mut self, plus: bool, span: &Span) {
+        let (offset, moved) = self.access();
+        // a node that has to check moves has a new offset, so it is never merged into the last one
+        if let Some(node) = self.nodes.last_mut().filter(|_| moved.is_empty()) {
+            let merged = match &mut node.instr {
+                Instr::Add(o, amount) if plus && *o == offset && *amount < MAX_RUN => {
+                    *amount += 1;
+                    true
+                }
+                Instr::Sub(o, amount) if !plus && *o == offset && *amount < MAX_RUN => {
+                    *amount += 1;
+                    true
+                }
+                _ => false,
+            };
+            if merged {
+                node.spans.push(span.clone());
+                node.ops += 1;
+                return;
+            }
+        }
+        let instr = if plus {
+            Instr::Add(offset, 1)
+        } else {
+            Instr::Sub(offset, 1)
+        };
+        self.push_access(instr, span, moved);
+    }
+
+    fn push_access(&mut self, instr: Instr, span: &Span, moved: Moves) {
+        let mut node = Node::new(instr, span);
+        node.moved = moved;
+        self.push(node);
+    }
+
+    fn op(&mut self, op: Op, span: &Span) {
+        match op {
+            Op::Plus => self.add(true, span),
+            Op::Minus => self.add(false, span),
+            Op::Left => self.step(-1, span),
+            Op::Right => self.step(1, span),
+            Op::Output => {
+                let (offset, moved) = self.access();
+                self.push_access(Instr::Output(offset), span, moved);
+            }
+            Op::Input => {
+                let (offset, moved) = self.access();
+                self.push_access(Instr::Input(offset), span, moved);
+            }
+            Op::Debug => {
+                self.flush_moves();
+                self.push(Node::new(Instr::Debug, span));
+            }
+            Op::Start | Op::End => unreachable!(),
+        }
+    }
+
+    // Returns instructions that do the same thing as a loop with the given body, if there are any,
+    // and the moves the first instruction has to check on the way to the loop's cell
+    fn pattern(&mut self, body: &[Block]) -> Option<(Vec<Instr>, Moves)> {
+        if let [op] = body {
+            if let Some(op @ Op::Minus) | Some(op @ Op::Plus) = op.op() {
+                let (offset, moved) = self.access();
+                return Some((vec![Instr::Clear(offset, op)], moved));
+            }
+        }
+        // a scan only checks the cells it stops on
+        let stride = scan_stride(body).filter(|stride| !self.check_every_move || stride.abs() == 1);
+        if let Some(stride) = stride {
+            self.flush_moves();
+            return Some((vec![Instr::Scan(stride)], Vec::new()));
+        }
+        // when overflow is trapped, which op fails first depends on the order of the loop body,
+        // and a multiply doesn't check the cells in between the ones it changes
+        if self.overflow == Overflow::Trap || self.check_every_move {
+            return None;
+        }
+        let targets = multiply_targets(body, self.overflow)?;
+        // the loop can't be run as a multiply if it goes all the way around a wrapping tape, as
+        // the cells it changes may be the loop cell or each other
+        if let TapeModel::Wrapping(len) = self.tape {
+            let low = targets.iter().map(|(o, _)| *o).min().unwrap_or(0).min(0);
+            let high = targets.iter().map(|(o, _)| *o).max().unwrap_or(0).max(0);
+            if (high - low) as usize >= len {
+                return None;
+            }
+        }
+        let (offset, moved) = self.access();
+        let mut instrs: Vec<Instr> = targets
+            .into_iter()
+            .map(|(target, factor)| Instr::Mul(offset, offset + target, factor))
+            .collect();
+        instrs.push(Instr::Clear(offset, Op::Minus));
+        Some((instrs, moved))
+    }
+
+    fn block(&mut self, block: &Block) {
+        match block {
+            Block::Op(op, span) => self.op(*op, span),
+            Block::Loop { body, start, end } => {
+                if end.is_some() {
+                    if let Some((instrs, mut moved)) = self.pattern(body) {
+                        let mut spans = Vec::new();
+                        block.spans(&mut spans);
+                        for instr in instrs {
+                            let spans = spans.clone();
+                            let moved = mem::take(&mut moved);
+                            // the '[' is counted once, by the Clear or Scan that ends the loop
+                            let ops = match instr {
+                                Instr::Clear(..) | Instr::Scan(_) => 1,
+                                _ => 0,
+                            };
+                            self.push(Node {
+                                instr,
+                                spans,
+                                moved,
+                                ops,
+                            });
+                        }
+                        return;
+                    }
+                }
+                self.flush_moves();
+                let open = self.nodes.len();
+                // the jump target is filled in once the body is lowered
+                self.push(Node::new(Instr::Open(0), start));
+                for block in body {
+                    self.block(block);
+                }
+                self.flush_moves();
+                match end {
+                    Some(end) => {
+                        let close = self.nodes.len();
+                        self.push(Node::new(Instr::Close(open + 1), end));
+                        self.nodes[open].instr = Instr::Open(close + 1);
+                    }
+                    None => self.unclosed.push(open),
+                }
+            }
+            Block::Extraneous(span) => {
+                let node = Node::new(Instr::Fault("Extraneous closing brace"), span);
+                self.push(node);
+            }
+        }
+    }
+}
+
+// '#'s are left out unless debug_op is set, so they don't stop loops matching patterns
+pub fn lower(code: &[(Op, Span)], config: &Config) -> Vec<Node> {
+    let code: Vec<(Op, Span)> = code
+        .iter()
+        .filter(|(op, _)| config.debug_op || *op != Op::Debug)
+        .cloned()
+        .collect();
+    let mut lowering = Lowering::new(config);
+    for block in &nest(&code) {
+        lowering.block(block);
+    }
+    lowering.flush_moves();
+    let end = lowering.nodes.len();
+    for open in lowering.unclosed.drain(..) {
+        lowering.nodes[open].instr = Instr::Open(end);
+    }
+    lowering.nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use source;
+    use source::Token;
+    use std::rc::Rc;
+
+    fn lower_nodes(code: &str, overflow: Overflow, debug_op: bool) -> Vec<Node> {
+        let source = Rc::new(source::File::from_string(code.to_string()));
+        let code: Vec<(Op, Span)> = source::lex(source)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Bf(op, span) => Some((op, span)),
+                _ => None,
+            })
+            .collect();
+        let mut config = Config::new_default();
+        config.overflow = overflow;
+        config.debug_op = debug_op;
+        lower(&code, &config)
+    }
+
+    fn lower_str_with(code: &str, overflow: Overflow, debug_op: bool) -> Vec<Instr> {
+        lower_nodes(code, overflow, debug_op)
+            .into_iter()
+            .map(|node| node.instr)
+            .collect()
+    }
+
+    fn lower_str(code: &str) -> Vec<Instr> {
+        lower_str_with(code, Overflow::Wrap, false)
+    }
+
+    #[test]
+    fn run_length() {
+        assert_eq!(
+            lower_str("+++--->>><"),
+            vec![
+                Instr::Add(0, 3),
+                Instr::Sub(0, 3),
+                Instr::Check(3),
+                Instr::Move(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(
+            lower_str(">+>>-.<,"),
+            vec![
+                Instr::Add(1, 1),
+                Instr::Sub(3, 1),
+                Instr::Output(3),
+                Instr::Input(2),
+                Instr::Move(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn moved_back_to_start() {
+        assert_eq!(lower_str("<<>>"), vec![Instr::Check(-2)]);
+    }
+
+    #[test]
+    fn unchecked_moves() {
+        // the offset and column of each move a node checks
+        let moved = |code: &str| -> Vec<Vec<(isize, u32)>> {
+            lower_nodes(code, Overflow::Wrap, false)
+                .iter()
+                .map(|node| node.moved.iter().map(|(o, s)| (*o, s.col())).collect())
+                .collect()
+        };
+        assert_eq!(
+            moved(">>+<<<.>>"),
+            vec![vec![(1, 0), (2, 1)], vec![(-1, 5)], vec![]]
+        );
+        assert_eq!(
+            moved("<<>[-]"),
+            vec![vec![(-1, 0), (-2, 1)], vec![], vec![]]
+        );
+        assert_eq!(moved(">>>"), vec![vec![(1, 0), (2, 1), (3, 2)]]);
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            lower_str("+[>+<--]"),
+            vec![
+                Instr::Add(0, 1),
+                Instr::Open(5),
+                Instr::Add(1, 1),
+                Instr::Sub(0, 2),
+                Instr::Close(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiply_loops() {
+        assert_eq!(
+            lower_str(">[->+++>>--<<<]"),
+            vec![
+                Instr::Mul(1, 2, 3),
+                Instr::Mul(1, 4, -2),
+                Instr::Clear(1, Op::Minus),
+                Instr::Move(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiply_loop_checks_extremes_first() {
+        assert_eq!(
+            lower_str("[<+>>>+<<-]"),
+            vec![
+                Instr::Mul(0, -1, 1),
+                Instr::Mul(0, 2, 1),
+                Instr::Clear(0, Op::Minus),
+            ]
+        );
+        assert_eq!(
+            lower_str("[>>+<+<-<<>>]"),
+            vec![
+                Instr::Mul(0, -2, 0),
+                Instr::Mul(0, 2, 1),
+                Instr::Mul(0, 1, 1),
+                Instr::Clear(0, Op::Minus),
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_loops() {
+        assert_eq!(
+            lower_str("+>[>]<<[<<<]+"),
+            vec![
+                Instr::Add(0, 1),
+                Instr::Move(1),
+                Instr::Scan(1),
+                Instr::Move(-2),
+                Instr::Scan(-3),
+                Instr::Add(0, 1),
+            ]
+        );
+        assert_eq!(lower_str("[><]")[0], Instr::Open(3));
+    }
+
+    #[test]
+    fn no_multiply_loops_when_overflow_is_trapped() {
+        assert_eq!(
+            lower_str_with("[->+<]", Overflow::Trap, false)[0],
+            Instr::Open(4)
+        );
+    }
+
+    #[test]
+    fn no_mixed_multiply_loops_when_overflow_saturates() {
+        for code in &["[->+-<]", "[+-->+<]", "[->-<>+<]"] {
+            assert!(
+                lower_str(code)
+                    .iter()
+                    .any(|instr| matches!(instr, Instr::Mul(..))),
+                "{:?} was not lowered to a multiply",
+                code
+            );
+            assert!(
+                matches!(
+                    lower_str_with(code, Overflow::Saturate, false)[0],
+                    Instr::Open(_)
+                ),
+                "{:?} was lowered to a multiply",
+                code
+            );
+        }
+        assert_eq!(
+            lower_str_with("[->++<]", Overflow::Saturate, false)[0],
+            Instr::Mul(0, 1, 2)
+        );
+    }
+
+    #[test]
+    fn not_multiply_loops() {
+        for code in &[
+            "[->+<<]", "[>+<]", "[-->+<]", "[+>+<]", "[->.<]", "[->[-]<]",
+        ] {
+            assert!(
+                !lower_str(code)
+                    .iter()
+                    .any(|instr| matches!(instr, Instr::Mul(..))),
+                "{:?} was lowered to a multiply",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn clear_loops() {
+        assert_eq!(
+            lower_str(">[-]>>[+]"),
+            vec![
+                Instr::Clear(1, Op::Minus),
+                Instr::Clear(3, Op::Plus),
+                Instr::Move(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_brackets() {
+        assert_eq!(
+            lower_str("]+[>"),
+            vec![
+                Instr::Fault("Extraneous closing brace"),
+                Instr::Add(0, 1),
+                Instr::Open(4),
+                Instr::Move(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_ops() {
+        let code = "+>#[-#]#+";
+        assert_eq!(lower_str(code), lower_str("+>[-]+"));
+        assert_eq!(
+            lower_str_with(code, Overflow::Wrap, true),
+            vec![
+                Instr::Add(0, 1),
+                Instr::Move(1),
+                Instr::Debug,
+                Instr::Open(7),
+                Instr::Sub(0, 1),
+                Instr::Debug,
+                Instr::Close(4),
+                Instr::Debug,
+                Instr::Add(0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_run() {
+        let code = "+".repeat(MAX_RUN as usize + 2);
+        assert_eq!(
+            lower_str(&code),
+            vec![Instr::Add(0, MAX_RUN), Instr::Add(0, 2)]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+// Most cells that can be logged before the iterations running are forgotten, so a loop around a
+// whole program doesn't use up memory
+const LOG_LIMIT: usize = 1 << 16;
+
+// Where the iteration of a running loop started
+#[derive(Clone)]
+struct Iteration {
+    ptr: isize,
+    mark: Option<usize>, // the length of the log when it started, or None if it has been forgotten
+    io_count: u64,       // the number of ops that did input or output before it started
+}
+
+// Finds loops that will never end. If an iteration of a loop ends with the pointer where it
+// started, and each cell it tested has the value it had when the iteration started, with no input
+// or output in between, the next iteration will do exactly the same, and so will every one after.
+pub struct LoopDetector<D> {
+    iterations: Vec<Iteration>, // the iteration of each running loop, outermost first
+    log: Vec<(isize, Option<D>)>, // each cell tested, or written along with its old value
+    io_count: u64,
+}
+
+impl<D: Cell> LoopDetector<D> {
+    // depth is the number of loops that are running
+    pub fn new(depth: usize) -> LoopDetector<D> {
+        let mut detector = LoopDetector {
+            iterations: Vec::new(),
+            log: Vec::new(),
+            io_count: 0,
+        };
+        detector.forget(depth);
+        detector
+    }
+
+    // forgets the iterations that are running, which should be done when the state is changed by
+    // anything but running forwards
+    pub fn forget(&mut self, depth: usize) {
+        let forgotten = Iteration {
+            ptr: 0,
+            mark: None,
+            io_count: 0,
+        };
+        self.iterations = vec![forgotten; depth];
+        self.log.clear();
+    }
+
+    fn log(&mut self, index: isize, old: Option<D>) {
+        if self.iterations.is_empty() {
+            return;
+        }
+        if self.log.len() >= LOG_LIMIT {
+            let depth = self.iterations.len();
+            self.forget(depth);
+        }
+        self.log.push((index, old));
+    }
+
+    pub fn tested(&mut self, index: isize) {
+        self.log(index, None);
+    }
+
+    pub fn written(&mut self, index: isize, old: D) {
+        self.log(index, Some(old));
+    }
+
+    pub fn io(&mut self) {
+        self.io_count += 1;
+    }
+
+    // a loop is starting with the pointer at ptr
+    pub fn enter(&mut self, ptr: isize) {
+        let iteration = Iteration {
+            ptr,
+            mark: Some(self.log.len()),
+            io_count: self.io_count,
+        };
+        self.iterations.push(iteration);
+    }
+
+    pub fn exit(&mut self) {
+        self.iterations.pop();
+        if self.iterations.is_empty() {
+            self.log.clear();
+        }
+    }
+
+    // The innermost loop is going back to its start with the pointer at ptr. Returns true if the
+    // iteration that just ended will be repeated forever, or starts the next one. If all_cells is
+    // set, every cell written is checked, not only the tested ones, for when changing a cell can
+    // be an error.
+    pub fn repeats<F: Fn(isize) -> D>(&mut self, ptr: isize, all_cells: bool, get_cell: F) -> bool {
+        let iteration = self.iterations.last().unwrap().clone();
+        if let Some(mark) = iteration.mark {
+            if iteration.ptr == ptr && iteration.io_count == self.io_count {
+                let log = &self.log[mark..];
+                let tested: HashSet<isize> = log
+                    .iter()
+                    .filter(|(_, old)| old.is_none())
+                    .map(|(index, _)| *index)
+                    .collect();
+                // the first write to each cell has the value it started with
+                let mut started: HashMap<isize, &D> = HashMap::new();
+                for (index, old) in log {
+                    if let Some(old) = old {
+                        started.entry(*index).or_insert(old);
+                    }
+                }
+                let unchanged = started.iter().all(|(index, old)| {
+                    (!all_cells && !tested.contains(index)) || **old == get_cell(*index)
+                });
+                if unchanged {
+                    return true;
+                }
+            }
+        }
+        if self.iterations.len() == 1 {
+            // nothing else needs what has been logged
+            self.log.clear();
+        }
+        let last = self.iterations.len() - 1;
+        self.iterations[last] = Iteration {
+            ptr,
+            mark: Some(self.log.len()),
+            io_count: self.io_count,
+        };
+        false
+    }
+}
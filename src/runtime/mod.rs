@@ -1,15 +1,102 @@
+//! Running Brainfuck code.
+//!
+//! [`debug::Runtime`] runs one op at a time, and can have more code added as it goes.
+//! [`fast::Runtime`] runs code lowered to an optimized form, and is the one to use when only the
+//! result matters.
+
+mod cell;
+mod condition;
+mod config;
+mod coverage;
 pub mod debug;
+mod encoding;
+pub mod fast;
+mod ir;
+mod loop_detector;
+mod observer;
 mod op;
+mod profile;
+mod snapshot;
+mod stream;
+mod tape;
+mod trace;
 
+pub use self::cell::{BigInt, Cell, Overflow};
+pub use self::condition::Condition;
+pub use self::config::{Config, Eof};
+pub use self::coverage::{Coverage, LcovRecord};
+pub use self::encoding::Encoding;
+pub use self::observer::{Event, Observer};
 pub use self::op::Op;
+pub use self::profile::{LoopProfile, Profiler};
+pub use self::stream::{Callbacks, Io, Stream};
+pub use self::tape::{Tape, TapeModel};
+pub use self::trace::Tracer;
+use source::{Span, Token};
+use std::time::Instant;
 
+/// Why a runtime stopped running. It can be run again to carry on from where it stopped.
 #[derive(PartialEq, Debug)]
 pub enum Abort {
+    /// The end of the code was reached
     Completed,
+    /// The number of instructions it was allowed to run were run
     InstrCapped,
+    /// The deadline given to set_deadline passed
+    TimedOut,
+    /// The pointer moved to a cell that would make the tape store more cells than
+    /// Config::tape_limit allows
+    TapeFull(::io::Issue),
+    /// ',' needs more input than has been queued, and the Io has none ready
     AwaitingInput,
+    /// The op at the span has a breakpoint on it, or is a '#' and Config::debug_op is set. The op
+    /// has not been run yet. Running again carries on from it without stopping there again.
+    Breakpoint(Span),
+    /// The op at the span changed a watched cell, or made a condition become true. The op has been
+    /// run.
+    Watchpoint(Span),
+    /// Running backwards got back to the first op run, or the oldest one still in the history
+    StartOfHistory,
+    /// The code did something it is not allowed to
     Error(::io::Issue),
 }
 
+// Instructions run between looking at the time, which is slow compared to running one
+const DEADLINE_INTERVAL: u64 = 4096;
+
+// If deadline has passed, only looking at the time once every DEADLINE_INTERVAL instructions
+fn past_deadline(deadline: Option<Instant>, instr_count: u64) -> bool {
+    match deadline {
+        Some(deadline) => {
+            instr_count.is_multiple_of(DEADLINE_INTERVAL) && Instant::now() >= deadline
+        }
+        None => false,
+    }
+}
+
+// The ops in tokens, leaving out the comments between them
+pub(crate) fn ops(tokens: &[Token]) -> Vec<(Op, Span)> {
+    tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Bf(op, span) => Some((*op, span.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+// An error from running the op at span, with a note for each loop it is in, innermost first
+fn error<'a, L: Iterator<Item = &'a Span>>(span: &Span, message: &str, loops: L) -> Abort {
+    let mut issue = span.issue(::io::RuntimeError, message);
+    for start in loops {
+        issue = issue.with_note(start.clone(), "In this loop");
+    }
+    if message == tape::PAST_LIMIT {
+        Abort::TapeFull(issue)
+    } else {
+        Abort::Error(issue)
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,40 @@
+use super::*;
+use source::Span;
+
+/// An op that debug::Runtime has just run
+pub struct Event<'a, D: 'a> {
+    pub count: u64,   // how many ops were run before this one
+    pub instr: usize, // the index of the op in the code
+    pub op: Op,
+    pub span: &'a Span,
+    pub ptr: isize,    // the pointer before the op was run
+    pub before: &'a D, // the value of the cell at ptr before the op was run
+    pub after: &'a D,  // the value of the cell at ptr after the op was run
+}
+
+/// Something that is told about every op debug::Runtime::run_with runs
+pub trait Observer<D> {
+    fn op_run(&mut self, event: &Event<D>);
+}
+
+/// Observes nothing
+impl<D> Observer<D> for () {
+    fn op_run(&mut self, _: &Event<D>) {}
+}
+
+/// Observes nothing when it is None
+impl<D, O: Observer<D>> Observer<D> for Option<O> {
+    fn op_run(&mut self, event: &Event<D>) {
+        if let Some(observer) = self {
+            observer.op_run(event);
+        }
+    }
+}
+
+/// Tells both observers about each op
+impl<D, A: Observer<D>, B: Observer<D>> Observer<D> for (A, B) {
+    fn op_run(&mut self, event: &Event<D>) {
+        self.0.op_run(event);
+        self.1.op_run(event);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt;
 
+/// A Brainfuck instruction
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Op {
     Plus,
@@ -10,6 +11,7 @@ pub enum Op {
     Input,
     Start,
     End,
+    Debug, // '#', only does anything if Config::debug_op is set
 }
 
 impl Op {
@@ -23,6 +25,7 @@ impl Op {
             ',' => Some(Op::Input),
             '[' => Some(Op::Start),
             ']' => Some(Op::End),
+            '#' => Some(Op::Debug),
             _ => None,
         }
     }
@@ -37,6 +40,7 @@ impl Op {
             Op::Input => ',',
             Op::Start => '[',
             Op::End => ']',
+            Op::Debug => '#',
         }
     }
 }
@@ -0,0 +1,98 @@
+use super::*;
+use source::{File, Span, Token};
+use std::cmp;
+
+/// Counts how many times each op is run by debug::Runtime::run_with
+pub struct Profiler {
+    code: Vec<(Op, Span)>,
+    counts: Vec<u64>, // times each op in code has been run
+    debug_op: bool,   // if '#' is an op, rather than a comment
+}
+
+/// How much a loop was run
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopProfile {
+    pub start: Span,     // the '['
+    pub end: Span,       // the ']'
+    pub entered: u64,    // times the '[' was run
+    pub iterations: u64, // times the body was run to the end
+    pub ops: u64,        // ops run in the loop, including its brackets and nested loops
+}
+
+impl Profiler {
+    /// Profiles the ops in tokens, which should be the same code the runtime runs with the same
+    /// config
+    pub fn new(tokens: &[Token], config: &Config) -> Profiler {
+        let code = ops(tokens);
+        Profiler {
+            counts: vec![0; code.len()],
+            code,
+            debug_op: config.debug_op,
+        }
+    }
+
+    /// The number of times the op at index instr in the code has been run
+    pub fn count(&self, instr: usize) -> u64 {
+        self.counts.get(instr).cloned().unwrap_or(0)
+    }
+
+    /// Every loop in the code that was run, the one the most ops were run in first
+    pub fn hottest_loops(&self) -> Vec<LoopProfile> {
+        let mut open = Vec::new();
+        let mut loops = Vec::new();
+        for (instr, (op, span)) in self.code.iter().enumerate() {
+            match op {
+                Op::Start => open.push(instr),
+                Op::End => {
+                    if let Some(start) = open.pop() {
+                        if self.counts[start] > 0 {
+                            loops.push(LoopProfile {
+                                start: self.code[start].1.clone(),
+                                end: span.clone(),
+                                entered: self.counts[start],
+                                iterations: self.counts[instr],
+                                ops: self.counts[start..=instr].iter().sum(),
+                            });
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        loops.sort_by_key(|profile| cmp::Reverse(profile.ops));
+        loops
+    }
+
+    /// The source code with the number of ops run on each line in front of it. Lines with no ops
+    /// have nothing in front of them.
+    pub fn annotate(&self, src: &File) -> String {
+        let lines: Vec<&str> = src.contents().lines().collect();
+        let mut line_counts: Vec<Option<u64>> = vec![None; lines.len()];
+        for ((op, span), count) in self.code.iter().zip(&self.counts) {
+            if *op == Op::Debug && !self.debug_op {
+                continue;
+            }
+            if let Some(line_count) = line_counts.get_mut(span.line() as usize) {
+                *line_count = Some(line_count.unwrap_or(0) + count);
+            }
+        }
+        let width = line_counts
+            .iter()
+            .map(|count| count.map_or(0, |count| count.to_string().len()))
+            .fold(1, cmp::max);
+        let mut annotated = String::new();
+        for (line, count) in lines.iter().zip(line_counts) {
+            let count = count.map_or(String::new(), |count| count.to_string());
+            annotated += &format!("{:>width$} | {}\n", count, line, width = width);
+        }
+        annotated
+    }
+}
+
+impl<D> Observer<D> for Profiler {
+    fn op_run(&mut self, event: &Event<D>) {
+        if let Some(count) = self.counts.get_mut(event.instr) {
+            *count += 1;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use super::*;
+use io::Issue;
+use source::Span;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+const HEADER: &str = "bft snapshot 1";
+
+// FNV-1a of the ops, which unlike std's hashers gives the same hash on every build
+pub fn hash_code(code: &[(Op, Span)]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (op, _) in code {
+        hash ^= op.get_char() as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+// The state of a debug::Runtime, as it is saved in a file. Each part is on its own line, starting
+// with its name, so snapshots can be read and attached to bug reports.
+pub struct Snapshot<D> {
+    pub code_hash: u64,
+    pub ptr: isize,
+    pub stack: Vec<usize>,
+    pub first_cell: isize, // index of the first cell in cells
+    pub cells: Vec<D>,
+    pub input: Vec<u8>, // queued input that has not been read yet
+    pub input_closed: bool,
+    pub instr_count: u64,
+}
+
+fn invalid(message: &str) -> Issue {
+    Issue::new(::io::Error, &format!("Snapshot is not valid: {}", message))
+}
+
+// parses each word of a line after its name
+fn parse_words<T: FromStr>(name: &str, words: &[&str]) -> Result<Vec<T>, Issue> {
+    words
+        .iter()
+        .map(|word| {
+            word.parse()
+                .map_err(|_| invalid(&format!("'{}' in '{}' is not a valid value", word, name)))
+        })
+        .collect()
+}
+
+fn parse_one<T: FromStr>(name: &str, words: &[&str]) -> Result<T, Issue> {
+    let mut values = parse_words(name, words)?;
+    match values.len() {
+        1 => Ok(values.remove(0)),
+        _ => Err(invalid(&format!("'{}' should have one value", name))),
+    }
+}
+
+impl<D: Cell> Snapshot<D> {
+    pub fn write<W: Write>(&self, mut output: W) -> ::std::io::Result<()> {
+        writeln!(output, "{}", HEADER)?;
+        writeln!(output, "code {:016x}", self.code_hash)?;
+        writeln!(output, "ptr {}", self.ptr)?;
+        write!(output, "stack")?;
+        for instr in &self.stack {
+            write!(output, " {}", instr)?;
+        }
+        write!(output, "\ncells {}", self.first_cell)?;
+        for cell in &self.cells {
+            write!(output, " {}", cell)?;
+        }
+        write!(output, "\ninput ")?;
+        for byte in &self.input {
+            write!(output, "{:02x}", byte)?;
+        }
+        writeln!(output, "\nclosed {}", self.input_closed)?;
+        writeln!(output, "instrs {}", self.instr_count)
+    }
+
+    pub fn read<R: Read>(mut input: R) -> Result<Snapshot<D>, Issue> {
+        let mut text = String::new();
+        if let Err(e) = input.read_to_string(&mut text) {
+            return Err(Issue::new(
+                ::io::Error,
+                &format!("Failed to read snapshot: {}", e),
+            ));
+        }
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid(&format!("it should start with '{}'", HEADER)));
+        }
+        // the words of the next line after its name, which must be the expected one
+        let mut line = |name: &str| -> Result<Vec<&str>, Issue> {
+            let mut words: Vec<&str> = lines.next().unwrap_or("").split_whitespace().collect();
+            if words.first() != Some(&name) {
+                return Err(invalid(&format!("expected a '{}' line", name)));
+            }
+            words.remove(0);
+            Ok(words)
+        };
+        let code_hash = match line("code")?[..] {
+            [hash] => u64::from_str_radix(hash, 16).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| invalid("'code' should have one hash"))?;
+        let ptr = parse_one("ptr", &line("ptr")?)?;
+        let stack: Vec<usize> = parse_words("stack", &line("stack")?)?;
+        if stack.is_empty() {
+            return Err(invalid("'stack' should not be empty"));
+        }
+        let cells = line("cells")?;
+        if cells.is_empty() {
+            return Err(invalid(
+                "'cells' should start with the index of the first cell",
+            ));
+        }
+        let first_cell = parse_one("cells", &cells[..1])?;
+        let cells = parse_words("cells", &cells[1..])?;
+        let input = match line("input")?[..] {
+            [] => Some(Vec::new()),
+            [hex] if hex.len() % 2 == 0 && hex.is_ascii() => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                .collect(),
+            _ => None,
+        }
+        .ok_or_else(|| invalid("'input' should be a string of hex bytes"))?;
+        let input_closed = parse_one("closed", &line("closed")?)?;
+        let instr_count = parse_one("instrs", &line("instrs")?)?;
+        Ok(Snapshot {
+            code_hash,
+            ptr,
+            stack,
+            first_cell,
+            cells,
+            input,
+            input_closed,
+            instr_count,
+        })
+    }
+}
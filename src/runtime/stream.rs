@@ -0,0 +1,159 @@
+use super::encoding::InputResult;
+use super::*;
+use std::collections::VecDeque;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+
+/// Where a runtime gets input from once what was queued is used up, and sends its output to
+pub trait Io {
+    /// Reads input into buffer like std::io::Read::read, with Ok(0) meaning the end of input. An
+    /// error of kind WouldBlock means no input is ready yet, and stops the runtime with
+    /// Abort::AwaitingInput so more can be queued.
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()>;
+}
+
+/// A closure only takes output, all input has to be queued on the runtime
+impl<F: FnMut(u8)> Io for F {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(ErrorKind::WouldBlock.into())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for byte in bytes {
+            self(*byte);
+        }
+        Ok(())
+    }
+}
+
+/// Input and output that are handled by a closure each
+pub struct Callbacks<R, W> {
+    read: R,
+    write: W,
+}
+
+impl<R, W> Callbacks<R, W>
+where
+    R: FnMut(&mut [u8]) -> io::Result<usize>,
+    W: FnMut(&[u8]) -> io::Result<()>,
+{
+    pub fn new(read: R, write: W) -> Callbacks<R, W> {
+        Callbacks { read, write }
+    }
+}
+
+impl<R, W> Io for Callbacks<R, W>
+where
+    R: FnMut(&mut [u8]) -> io::Result<usize>,
+    W: FnMut(&[u8]) -> io::Result<()>,
+{
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        (self.read)(buffer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        (self.write)(bytes)
+    }
+}
+
+/// Input and output from a std::io reader and writer
+pub struct Stream<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: Read, W: Write> Stream<R, W> {
+    pub fn new(input: R, output: W) -> Stream<R, W> {
+        Stream { input, output }
+    }
+}
+
+impl<R: Read, W: Write> Io for Stream<R, W> {
+    /// output is flushed first, so a prompt is seen before reading blocks waiting for an answer
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.output.flush()?;
+        self.input.read(buffer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)
+    }
+}
+
+// Input that has been queued or read from an Io, but not used by ',' yet
+pub struct InputBuffer {
+    bytes: VecDeque<u8>,
+    closed: bool,   // if no more input will come once bytes is empty
+    taken: Vec<u8>, // the bytes the last value read was made from
+}
+
+impl InputBuffer {
+    pub fn new() -> InputBuffer {
+        InputBuffer {
+            bytes: VecDeque::new(),
+            closed: false,
+            taken: Vec::new(),
+        }
+    }
+
+    pub fn queue(&mut self, input: &[u8]) {
+        self.bytes.extend(input);
+    }
+
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub fn queued(&self) -> &VecDeque<u8> {
+        &self.bytes
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn taken(&self) -> &[u8] {
+        &self.taken
+    }
+
+    // puts bytes back at the front of the input, so they are read again
+    pub fn unread(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter().rev() {
+            self.bytes.push_front(*byte);
+        }
+    }
+
+    // takes the next value for ',', reading more input from io if what's queued isn't enough
+    pub fn read<D: Cell, I: Io + ?Sized>(
+        &mut self,
+        encoding: Encoding,
+        io: &mut I,
+    ) -> InputResult<D> {
+        loop {
+            // a value is never made from more than 4 bytes
+            let len = self.bytes.len();
+            self.taken.clear();
+            self.taken.extend(self.bytes.iter().take(4));
+            match encoding.read(&mut self.bytes, self.closed) {
+                InputResult::Waiting => {
+                    let mut buffer = [0; 4096];
+                    match io.read(&mut buffer) {
+                        Ok(0) => self.closed = true,
+                        Ok(len) => self.bytes.extend(&buffer[..len]),
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                            return InputResult::Waiting
+                        }
+                        Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                        Err(_) => return InputResult::Error("Failed to read input"),
+                    }
+                }
+                result => {
+                    self.taken.truncate(len - self.bytes.len());
+                    return result;
+                }
+            }
+        }
+    }
+}
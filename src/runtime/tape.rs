@@ -0,0 +1,197 @@
+use super::*;
+
+const LEFT_OF_START: &str = "Pointer moved left of the starting point";
+const PAST_END: &str = "Pointer moved past the end of the tape";
+pub(crate) const PAST_LIMIT: &str = "Pointer moved past the tape size limit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TapeModel {
+    RightInfinite,   // starts at cell 0 and grows to the right as needed
+    Infinite,        // grows in both directions as needed
+    Bounded(usize),  // a fixed number of cells, moving off either end is an error
+    Wrapping(usize), // a fixed number of cells, moving off one end comes back on the other
+}
+
+/// The cells of a runtime, laid out as set by its TapeModel
+// Cells at negative indices are stored in reverse order in left, so cell -1 is left[0]
+pub struct Tape<D> {
+    model: TapeModel,
+    limit: Option<usize>, // the most cells that can be stored
+    right: Vec<D>,
+    left: Vec<D>,
+}
+
+// Only every step cells are looked at, so a stride of 1 finds the first zero
+fn find_zero<'a, D: Cell, I: Iterator<Item = &'a D>>(cells: I, step: usize) -> Option<usize> {
+    cells.step_by(step).position(|c| *c == D::zero())
+}
+
+impl<D: Cell> Tape<D> {
+    pub fn new(model: TapeModel) -> Tape<D> {
+        Tape {
+            model,
+            limit: None,
+            right: Vec::new(),
+            left: Vec::new(),
+        }
+    }
+
+    pub fn get_model(&self) -> TapeModel {
+        self.model
+    }
+
+    /// cells that no longer fit on the tape are dropped
+    pub fn set_model(&mut self, model: TapeModel) {
+        self.model = model;
+        match model {
+            TapeModel::RightInfinite | TapeModel::Bounded(_) | TapeModel::Wrapping(_) => {
+                self.left.clear()
+            }
+            TapeModel::Infinite => (),
+        }
+        if let TapeModel::Bounded(len) | TapeModel::Wrapping(len) = model {
+            self.right.truncate(len);
+        }
+    }
+
+    /// Stops the pointer moving to a cell that would make the tape store more than limit cells.
+    /// Cells that are already stored are kept even if there are more of them.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// if the pointer can be on the cell at index, which may be past the limit
+    pub fn contains(&self, index: isize) -> bool {
+        match self.model {
+            TapeModel::RightInfinite => index >= 0,
+            TapeModel::Bounded(len) | TapeModel::Wrapping(len) => {
+                index >= 0 && (index as usize) < len
+            }
+            TapeModel::Infinite => true,
+        }
+    }
+
+    // the index a cell is stored at, or None if it is not on the tape
+    fn slot(&self, index: isize) -> Option<(bool, usize)> {
+        match self.model {
+            TapeModel::RightInfinite if index < 0 => None,
+            TapeModel::Bounded(len) if index < 0 || index as usize >= len => None,
+            TapeModel::Wrapping(0) => None,
+            TapeModel::Wrapping(len) => Some((true, index.rem_euclid(len as isize) as usize)),
+            _ if index < 0 => Some((false, (-index - 1) as usize)),
+            _ => Some((true, index as usize)),
+        }
+    }
+
+    /// The index of the first cell that is stored, and the cells from there on. Cells that are not
+    /// stored are zero.
+    pub fn stored(&self) -> (isize, Vec<D>) {
+        let cells = self.left.iter().rev().chain(self.right.iter());
+        (-(self.left.len() as isize), cells.cloned().collect())
+    }
+
+    /// cells off the tape always read as zero
+    pub fn get(&self, index: isize) -> D {
+        let cell = match self.slot(index) {
+            Some((true, i)) => self.right.get(i),
+            Some((false, i)) => self.left.get(i),
+            None => None,
+        };
+        cell.cloned().unwrap_or_else(D::zero)
+    }
+
+    /// Writes to cells off the tape are ignored. So are writes of zero to cells that aren't stored,
+    /// which already read as zero, so the cells that count towards the limit don't depend on how
+    /// the code that left them at zero was run.
+    pub fn set(&mut self, index: isize, value: D) {
+        let (cells, i) = match self.slot(index) {
+            Some((true, i)) => (&mut self.right, i),
+            Some((false, i)) => (&mut self.left, i),
+            None => return,
+        };
+        if i >= cells.len() {
+            if value == D::zero() {
+                return;
+            }
+            cells.resize(i + 1, D::zero());
+        }
+        cells[i] = value;
+    }
+
+    /// where the pointer ends up after moving from ptr, or the error message if it can't move there
+    pub fn offset(&self, ptr: isize, by: isize) -> Result<isize, &'static str> {
+        let index = ptr + by;
+        let index = match self.model {
+            TapeModel::RightInfinite | TapeModel::Bounded(_) if index < 0 => Err(LEFT_OF_START),
+            TapeModel::Bounded(len) if index as usize >= len => Err(PAST_END),
+            TapeModel::Wrapping(0) => Err(PAST_END),
+            TapeModel::Wrapping(len) => Ok(index.rem_euclid(len as isize)),
+            _ => Ok(index),
+        }?;
+        // every cell that is written to has been moved to first, so checking moves is enough to
+        // keep the tape inside the limit
+        if let Some(limit) = self.limit {
+            let (left, right) = if index < 0 {
+                (self.left.len().max(index.unsigned_abs()), self.right.len())
+            } else {
+                (self.left.len(), self.right.len().max(index as usize + 1))
+            };
+            if left + right > limit {
+                return Err(PAST_LIMIT);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Where the pointer ends up if it moves by stride until it finds a zero cell, and how many
+    /// moves that takes, or None if it would never find one. Cells are searched in bulk rather than
+    /// one move at a time.
+    pub fn scan(&self, ptr: isize, stride: isize) -> Result<Option<(isize, usize)>, &'static str> {
+        if let TapeModel::Wrapping(len) = self.model {
+            // every cell that can be reached has been checked after len moves
+            let mut index = ptr;
+            for moves in 0..len {
+                if self.get(index) == D::zero() {
+                    return Ok(Some((index, moves)));
+                }
+                index = self.offset(index, stride)?;
+            }
+            return Ok(None);
+        }
+        let step = stride.unsigned_abs();
+        let mut index = ptr;
+        let mut moved = 0;
+        loop {
+            // search the side of the tape index is on, cells past the stored ones are all zero
+            let (cells, i) = if index < 0 {
+                (&self.left, (-index - 1) as usize)
+            } else {
+                (&self.right, index as usize)
+            };
+            let away_from_start = (index < 0) == (stride < 0);
+            let found = if i >= cells.len() {
+                Some(0)
+            } else if away_from_start {
+                find_zero(cells[i..].iter(), step)
+                    .or_else(|| Some((cells.len() - i).div_ceil(step)))
+            } else {
+                find_zero(cells[..=i].iter().rev(), step)
+            };
+            match found {
+                Some(moves) => {
+                    let index = self.offset(index, stride * moves as isize)?;
+                    return Ok(Some((index, moved + moves)));
+                }
+                // continue on the other side of the tape from the first index past the start
+                None => {
+                    let moves = i / step + 1;
+                    index += stride * moves as isize;
+                    moved += moves;
+                    if self.model != TapeModel::Infinite {
+                        return self.offset(index, 0).map(|index| Some((index, moved)));
+                    }
+                }
+            }
+        }
+    }
+}
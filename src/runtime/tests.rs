@@ -2,53 +2,96 @@ use super::*;
 
 struct TestCase {
     code: &'static str,
+    first_cell: isize, // index of the first cell in initial_data and expected_data
     initial_data: Vec<u8>,
-    initial_ptr: usize,
+    initial_ptr: isize,
     input: &'static str,
+    close_input: bool, // if ',' should act on the end of input instead of waiting for more
     expected_data: Vec<u8>,
-    expected_ptr: usize,
+    expected_ptr: isize,
     expected_output: &'static str,
+    config: Config,
 }
 
 impl TestCase {
     fn new() -> TestCase {
         TestCase {
             code: "",
+            first_cell: 0,
             initial_data: vec![],
             initial_ptr: 0,
             input: "",
+            close_input: false,
             expected_data: vec![],
             expected_ptr: 0,
             expected_output: "",
+            config: Config::new_default(),
         }
     }
 
     fn run(self) {
         let source = ::std::rc::Rc::new(::source::File::from_string(self.code.to_string()));
+        let tokens = ::source::lex(source);
+        self.run_debug(&tokens);
+        self.run_fast(&tokens);
+    }
+
+    fn run_debug(&self, tokens: &[::source::Token]) {
         let mut runtime = debug::Runtime::<u8>::new();
+        runtime.set_config(self.config);
         for i in 0..self.initial_data.len() {
-            runtime.set_cell(i, self.initial_data[i]);
+            runtime.set_cell(self.first_cell + i as isize, self.initial_data[i]);
         }
         runtime.set_ptr(self.initial_ptr);
         runtime.queue_input_str(self.input);
-        let mut s = ::source::span::Generator::new(source.clone());
-        let mut tokens = ::source::lex(source);
-        // let tokens = code.iter().map(|op| op.token(s.span(0))).collect();
-        runtime.add_tokens(&tokens);
-        let mut result_output = String::new();
+        if self.close_input {
+            runtime.close_input();
+        }
+        runtime.add_tokens(tokens);
+        let mut result_output = Vec::new();
         assert_eq!(
-            runtime.run(Some(10000), &mut |c| result_output.push(c)),
+            runtime.run(Some(10000), &mut |byte| result_output.push(byte)),
             Abort::Completed
         );
-        assert_eq!(self.expected_output, result_output);
+        assert_eq!(self.expected_output.as_bytes(), &result_output[..]);
         let result_ptr = runtime.get_ptr();
         assert_eq!(self.expected_ptr, result_ptr);
         let mut result_data = Vec::new();
         for i in 0..self.expected_data.len() {
-            result_data.push(runtime.get_cell(i));
+            result_data.push(runtime.get_cell(self.first_cell + i as isize));
         }
         assert_eq!(self.expected_data, result_data);
     }
+
+    fn run_fast(&self, tokens: &[::source::Token]) {
+        let mut runtime = fast::Runtime::<u8>::new(tokens);
+        runtime.set_config(self.config);
+        for i in 0..self.initial_data.len() {
+            runtime.set_cell(self.first_cell + i as isize, self.initial_data[i]);
+        }
+        runtime.set_ptr(self.initial_ptr);
+        runtime.queue_input_str(self.input);
+        if self.close_input {
+            runtime.close_input();
+        }
+        let mut result_output = Vec::new();
+        assert_eq!(
+            runtime.run(Some(10000), &mut |byte| result_output.push(byte)),
+            Abort::Completed,
+            "fast runtime"
+        );
+        assert_eq!(
+            self.expected_output.as_bytes(),
+            &result_output[..],
+            "fast runtime output"
+        );
+        assert_eq!(self.expected_ptr, runtime.get_ptr(), "fast runtime pointer");
+        let mut result_data = Vec::new();
+        for i in 0..self.expected_data.len() {
+            result_data.push(runtime.get_cell(self.first_cell + i as isize));
+        }
+        assert_eq!(self.expected_data, result_data, "fast runtime data");
+    }
 }
 
 #[test]
@@ -180,3 +223,1691 @@ fn wrapping_add_and_subtract() {
 
     test.run();
 }
+
+#[test]
+fn skip_nested_loops() {
+    let mut test = TestCase::new();
+
+    test.code = "[[+]>+[-]]+";
+    test.expected_data = vec![1, 0];
+    test.expected_ptr = 0;
+
+    test.run();
+}
+
+#[test]
+fn loop_closed_by_later_tokens() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("[+".to_string()));
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    assert_eq!(runtime.run(Some(10000), &mut |_| ()), Abort::Completed);
+    let source = ::std::rc::Rc::new(::source::File::from_string("]+".to_string()));
+    runtime.add_tokens(&::source::lex(source));
+    assert_eq!(runtime.run(Some(10000), &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.get_cell(0), 1);
+}
+
+#[test]
+fn stepping_through_loops() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+[>[-]<-]+".to_string()));
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    let mut positions = vec![];
+    while let Some(span) = runtime.next_span() {
+        positions.push((span.start_byte(), runtime.loop_depth()));
+        assert_eq!(runtime.run(Some(1), &mut |_| ()), Abort::InstrCapped);
+    }
+    assert_eq!(
+        positions,
+        [
+            (0, 0),
+            (1, 0),
+            (2, 1),
+            (3, 1),
+            (6, 1),
+            (7, 1),
+            (8, 1),
+            (9, 0)
+        ]
+    );
+    assert_eq!(runtime.run(Some(1), &mut |_| ()), Abort::Completed);
+}
+
+#[test]
+fn hello_world() {
+    let mut test = TestCase::new();
+
+    test.code = include_str!("../../bf/hello_world.bf");
+    test.expected_data = vec![0, 87, 100, 33, 10];
+    test.expected_ptr = 4;
+    test.expected_output = "Hello World!\n";
+
+    test.run();
+}
+
+#[test]
+fn offsets_around_loops() {
+    let mut test = TestCase::new();
+
+    test.code = ">>+<-<[-]>>[<+>-]<<<>>+>>[+]<<.";
+    test.initial_data = vec![4, 3, 2, 0, 200];
+    test.initial_ptr = 1;
+    test.expected_data = vec![4, 0, 3, 0, 0];
+    test.expected_ptr = 2;
+    test.expected_output = "\u{3}";
+
+    test.run();
+}
+
+#[test]
+fn left_of_start_is_an_error() {
+    for code in &["<", "+<+", "<>", "[-]<<>>+", "+[-<+>]"] {
+        let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+        let tokens = ::source::lex(source);
+        let mut runtime = debug::Runtime::<u8>::new();
+        runtime.add_tokens(&tokens);
+        match runtime.run(Some(10000), &mut |_| ()) {
+            Abort::Error(_) => (),
+            a => panic!("debug runtime ran {:?} with result {:?}", code, a),
+        }
+        let mut runtime = fast::Runtime::<u8>::new(&tokens);
+        match runtime.run(Some(10000), &mut |_| ()) {
+            Abort::Error(_) => (),
+            a => panic!("fast runtime ran {:?} with result {:?}", code, a),
+        }
+    }
+}
+
+#[test]
+fn multiply_loop() {
+    let mut test = TestCase::new();
+
+    test.code = "[->++>>---<<<]>>>>[-<+>]";
+    test.initial_data = vec![3, 1, 0, 2, 2];
+    test.expected_data = vec![0, 7, 0, 251, 0];
+    test.expected_ptr = 4;
+
+    test.run();
+}
+
+#[test]
+fn multiply_loop_wraps() {
+    let mut test = TestCase::new();
+
+    test.code = "[->+++++<]>>[-<<+>>]";
+    test.initial_data = vec![200, 10, 100];
+    test.expected_data = vec![100, 242, 0];
+    test.expected_ptr = 2;
+
+    test.run();
+}
+
+#[test]
+fn multiply_loop_skipped() {
+    let mut test = TestCase::new();
+
+    test.code = "[-<+>]";
+    test.initial_data = vec![0];
+    test.expected_data = vec![0];
+
+    test.run();
+}
+
+#[test]
+fn scan_right() {
+    let mut test = TestCase::new();
+
+    test.code = "[>]+>[>>>]+>>[>>]+";
+    test.initial_data = vec![1, 2, 0, 5, 6, 7, 0, 0, 3];
+    test.expected_data = vec![1, 2, 1, 5, 6, 7, 1, 0, 3, 0, 1];
+    test.expected_ptr = 10;
+
+    test.run();
+}
+
+#[test]
+fn scan_left() {
+    let mut test = TestCase::new();
+
+    test.code = "[<<]+";
+    test.initial_data = vec![1, 0, 0, 3, 4, 1, 6];
+    test.initial_ptr = 6;
+    test.expected_data = vec![1, 0, 1, 3, 4, 1, 6];
+    test.expected_ptr = 2;
+
+    test.run();
+}
+
+#[test]
+fn scan_left_of_start_is_an_error() {
+    assert_eq!(
+        run_error("[<<<]", Config::new_default(), &[1, 1, 1, 1, 1], 4),
+        "Pointer moved left of the starting point"
+    );
+}
+
+// runs code in both runtimes, and returns why each stopped
+fn run_aborts(code: &str, config: Config, data: &[u8], ptr: isize) -> (Abort, Abort) {
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    for (i, value) in data.iter().enumerate() {
+        runtime.set_cell(i as isize, *value);
+        fast.set_cell(i as isize, *value);
+    }
+    runtime.set_ptr(ptr);
+    fast.set_ptr(ptr);
+    let expected = runtime.run(Some(10000), &mut |_| ());
+    let result = fast.run(Some(10000), &mut |_| ());
+    (expected, result)
+}
+
+// runs code that is expected to fail in both runtimes, and returns the errors from each
+fn run_errors(code: &str, config: Config, data: &[u8], ptr: isize) -> (::io::Issue, ::io::Issue) {
+    match run_aborts(code, config, data, ptr) {
+        (Abort::Error(a), Abort::Error(b)) => (a, b),
+        (a, b) => panic!("expected errors, got {:?} and {:?}", a, b),
+    }
+}
+
+// runs code that is expected to fail in both runtimes, and returns the error message
+fn run_error(code: &str, config: Config, data: &[u8], ptr: isize) -> String {
+    let (a, b) = run_errors(code, config, data, ptr);
+    assert_eq!(a.message, b.message);
+    a.message
+}
+
+#[test]
+fn bounded_tape() {
+    let mut test = TestCase::new();
+
+    test.code = ">>+<[<]+";
+    test.config.tape = TapeModel::Bounded(3);
+    test.initial_data = vec![0, 4, 0];
+    test.expected_data = vec![1, 4, 1];
+
+    test.run();
+}
+
+#[test]
+fn bounded_tape_errors() {
+    let mut config = Config::new_default();
+    config.tape = TapeModel::Bounded(3);
+    let past_end = "Pointer moved past the end of the tape";
+    assert_eq!(run_error(">>>", config, &[], 0), past_end);
+    assert_eq!(run_error("+[>+]", config, &[], 0), past_end);
+    assert_eq!(run_error("[>]", config, &[1, 1, 1], 0), past_end);
+    assert_eq!(
+        run_error("[-<+>]", config, &[1], 0),
+        "Pointer moved left of the starting point"
+    );
+}
+
+#[test]
+fn tape_limit() {
+    let mut test = TestCase::new();
+
+    test.code = ">>+<[<]+";
+    test.config.tape_limit = Some(3);
+    test.expected_data = vec![0, 1, 1];
+    test.expected_ptr = 1;
+
+    test.run();
+}
+
+#[test]
+fn tape_limit_errors() {
+    let past_limit = |code: &str, tape: TapeModel, limit: usize, data: &[u8]| {
+        let mut config = Config::new_default();
+        config.tape = tape;
+        config.tape_limit = Some(limit);
+        match run_aborts(code, config, data, 0) {
+            (Abort::TapeFull(a), Abort::TapeFull(b)) => {
+                assert_eq!(a.message, "Pointer moved past the tape size limit");
+                assert_eq!(b.message, a.message);
+            }
+            (a, b) => panic!("expected a full tape, got {:?} and {:?}", a, b),
+        }
+    };
+    past_limit("+[>+]", TapeModel::RightInfinite, 100, &[]);
+    past_limit("[>]", TapeModel::RightInfinite, 3, &[1, 1, 1]);
+    past_limit("+[<+]", TapeModel::Infinite, 100, &[]);
+    // cells that are stored count, even if the pointer has never been on them
+    past_limit("<", TapeModel::Infinite, 3, &[1, 1, 1]);
+    past_limit(">>>", TapeModel::Bounded(10), 3, &[]);
+    // a wrapping tape can go past the limit and back inside it
+    past_limit("<<+", TapeModel::Wrapping(4), 3, &[]);
+    // cells written on one side of the start leave less room on the other
+    past_limit("><<+>>", TapeModel::Infinite, 2, &[]);
+    // cells that are left at zero aren't stored, however the loop clearing them is run
+    let mut config = Config::new_default();
+    config.tape = TapeModel::Infinite;
+    config.tape_limit = Some(2);
+    assert_eq!(
+        run_aborts("<<[-]>[->+-<]>>+", config, &[], 0),
+        (Abort::Completed, Abort::Completed)
+    );
+}
+
+#[test]
+fn deadline() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+[]".to_string()));
+    let tokens = ::source::lex(source);
+    let deadline = Some(::std::time::Instant::now());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&tokens);
+    runtime.set_deadline(deadline);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::TimedOut);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    assert_eq!(fast.run(Some(10000), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.instr_count(), 10000);
+    fast.set_deadline(deadline);
+    assert_eq!(fast.run(None, &mut |_| ()), Abort::TimedOut);
+}
+
+#[test]
+fn both_runtimes_count_ops() {
+    let mut saturate = Config::new_default();
+    saturate.overflow = Overflow::Saturate;
+    let mut wrapping = Config::new_default();
+    wrapping.tape = TapeModel::Wrapping(6);
+    for (code, config) in &[
+        ("+++[>++<-]>[-]<>>>", Config::new_default()),
+        (">><<>>>><+<", Config::new_default()),
+        // clear, multiply and scan loops count each time around
+        ("+++++[>+++[>++<-]<-]>>[>>]<<<-[+]", Config::new_default()),
+        ("-[-]+++[->>+<<]>>>+>+<[>]", wrapping),
+        ("-->>+++[-<<+>>]<<[-]", saturate),
+    ] {
+        let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+        let tokens = ::source::lex(source);
+        let mut runtime = debug::Runtime::<u8>::new();
+        runtime.set_config(*config);
+        runtime.add_tokens(&tokens);
+        let mut fast = fast::Runtime::<u8>::new(&tokens);
+        fast.set_config(*config);
+        assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+        assert_eq!(fast.run(None, &mut |_| ()), Abort::Completed);
+        assert_eq!(fast.instr_count(), runtime.instr_count(), "{:?}", code);
+    }
+}
+
+#[test]
+fn instr_cap_counts_ops() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+++[>++<-]>.".to_string()));
+    let mut fast = fast::Runtime::<u8>::new(&::source::lex(source));
+    // the multiply loop is run all at once, going past the cap
+    assert_eq!(fast.run(Some(2), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.instr_count(), 3);
+    assert_eq!(fast.run(Some(2), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.instr_count(), 22);
+    assert_eq!(fast.run(Some(2), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.instr_count(), 24);
+    assert_eq!(fast.run(Some(2), &mut |_| ()), Abort::Completed);
+}
+
+#[test]
+fn instr_cap_of_zero() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+>+".to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    assert_eq!(runtime.run(Some(0), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.run(Some(0), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!((runtime.instr_count(), fast.instr_count()), (0, 0));
+    assert_eq!((runtime.get_cell(0), fast.get_cell(0)), (0, 0));
+}
+
+#[test]
+fn wrapping_tape() {
+    let mut test = TestCase::new();
+
+    test.code = "<+>>>>>-<<[>]+";
+    test.config.tape = TapeModel::Wrapping(4);
+    test.initial_data = vec![1, 0, 1, 1];
+    test.expected_data = vec![1, 0, 1, 2];
+    test.expected_ptr = 0;
+
+    test.run();
+}
+
+#[test]
+fn wrapping_tape_offsets() {
+    let mut test = TestCase::new();
+
+    test.code = "<<+>>[-<<<+>>>]>>>[<+>-]";
+    test.config.tape = TapeModel::Wrapping(5);
+    test.initial_data = vec![3, 0, 0, 2, 0];
+    test.expected_data = vec![0, 0, 6, 0, 0];
+    test.expected_ptr = 3;
+
+    test.run();
+}
+
+#[test]
+fn wrapping_tape_shorter_than_a_loop() {
+    let mut config = Config::new_default();
+    config.tape = TapeModel::Wrapping(2);
+    // the '+' is on the loop cell, so the loop never ends
+    let aborts = run_aborts("[->>+<<]", config, &[1], 0);
+    assert_eq!(aborts, (Abort::InstrCapped, Abort::InstrCapped));
+    config.tape = TapeModel::Wrapping(1);
+    let mut test = TestCase::new();
+    test.code = "[<++>-]";
+    test.config = config;
+    test.initial_data = vec![254];
+    test.expected_data = vec![0];
+    test.run();
+}
+
+#[test]
+fn wrapping_scan_never_ends() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("[>>]".to_string()));
+    let tokens = ::source::lex(source);
+    let mut config = Config::new_default();
+    config.tape = TapeModel::Wrapping(4);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    for i in 0..4 {
+        runtime.set_cell(i, (i % 2) as u8);
+        fast.set_cell(i, (i % 2) as u8);
+    }
+    runtime.set_ptr(1);
+    fast.set_ptr(1);
+    assert_eq!(runtime.run(Some(10000), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.run(Some(10000), &mut |_| ()), Abort::InstrCapped);
+}
+
+#[test]
+fn infinite_tape() {
+    let mut test = TestCase::new();
+
+    test.code = "[<]+[<<]-[>>>>>>>]+<<<<<<<<<<[-<+>]";
+    test.config.tape = TapeModel::Infinite;
+    test.first_cell = -11;
+    test.initial_data = vec![0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 1, 1, 1];
+    test.initial_ptr = 2;
+    test.expected_data = vec![0, 0, 0, 0, 0, 0, 0, 255, 1, 1, 1, 1, 1, 1, 1];
+    test.expected_ptr = -7;
+
+    test.run();
+}
+
+#[test]
+fn saturating_cells() {
+    let mut test = TestCase::new();
+
+    test.code = "++>-->[-]>[->+++<]";
+    test.config.overflow = Overflow::Saturate;
+    test.initial_data = vec![254, 1, 7, 100, 10];
+    test.expected_data = vec![255, 0, 0, 0, 255];
+    test.expected_ptr = 3;
+
+    test.run();
+}
+
+#[test]
+fn saturating_cells_in_loops() {
+    let mut config = Config::new_default();
+    config.overflow = Overflow::Saturate;
+    // the '+' saturates before the '-' takes it back down, so the loop is not a multiply
+    let code = format!(">{}<++[->+-<]>-.", "+".repeat(255));
+    let (output, data) = run_cells::<u8>(&code, config, b"", 2);
+    assert_eq!(output, vec![253]);
+    assert_eq!(data, vec![0, 253]);
+    // the loop cell saturates on the first iteration, so it takes 254 of them to get to zero
+    let (output, data) =
+        run_cells::<u8>(&format!(">{}[+--<+>]<.", "+".repeat(255)), config, b"", 2);
+    assert_eq!(output, vec![254]);
+    assert_eq!(data, vec![254, 0]);
+}
+
+#[test]
+fn saturating_clear_never_ends() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("[+]".to_string()));
+    let tokens = ::source::lex(source);
+    let mut config = Config::new_default();
+    config.overflow = Overflow::Saturate;
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    runtime.set_cell(0, 3);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    fast.set_cell(0, 3);
+    assert_eq!(runtime.run(Some(10000), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(fast.run(Some(10000), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(runtime.get_cell(0), 255);
+}
+
+#[test]
+fn trapped_overflow() {
+    let mut config = Config::new_default();
+    config.overflow = Overflow::Trap;
+    for (code, data, message) in &[
+        ("+", 255, "Cell overflowed"),
+        ("-", 0, "Cell underflowed"),
+        ("+++++", 253, "Cell overflowed"),
+        ("[+]", 1, "Cell overflowed"),
+        ("+[->-<]", 1, "Cell underflowed"),
+    ] {
+        let (a, b) = run_errors(code, config, &[*data], 0);
+        assert_eq!(&a.message, message);
+        assert_eq!(a, b, "running {:?}", code);
+    }
+}
+
+#[test]
+fn trapped_overflow_that_does_not_happen() {
+    let mut test = TestCase::new();
+
+    test.code = "++>--[-]>[->+<]";
+    test.config.overflow = Overflow::Trap;
+    test.initial_data = vec![253, 2, 2, 3];
+    test.expected_data = vec![255, 0, 0, 5];
+    test.expected_ptr = 2;
+
+    test.run();
+}
+
+// runs code with cells of type D and all of its input in both runtimes, and returns the output
+// and the first cells
+fn run_cells<D: Cell>(code: &str, config: Config, input: &[u8], cells: usize) -> (Vec<u8>, Vec<D>) {
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<D>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    runtime.queue_input(input);
+    runtime.close_input();
+    let mut fast = fast::Runtime::<D>::new(&tokens);
+    fast.set_config(config);
+    fast.queue_input(input);
+    fast.close_input();
+    let mut output = Vec::new();
+    let mut fast_output = Vec::new();
+    assert_eq!(
+        runtime.run(Some(100000), &mut |byte| output.push(byte)),
+        Abort::Completed
+    );
+    assert_eq!(
+        fast.run(Some(100000), &mut |byte| fast_output.push(byte)),
+        Abort::Completed
+    );
+    assert_eq!(output, fast_output, "fast runtime output");
+    let data: Vec<D> = (0..cells as isize).map(|i| runtime.get_cell(i)).collect();
+    let fast_data: Vec<D> = (0..cells as isize).map(|i| fast.get_cell(i)).collect();
+    assert!(data == fast_data, "fast runtime data");
+    (output, data)
+}
+
+#[test]
+fn wide_cells() {
+    let config = Config::new_default();
+    let code = "++++++++[->++++++++[->++++++++<]<]";
+    assert_eq!(run_cells::<u16>(code, config, b"", 3).1, vec![0, 0, 512]);
+    assert_eq!(run_cells::<u32>("-", config, b"", 1).1, vec![u32::MAX]);
+    assert_eq!(run_cells::<u64>("-", config, b"", 1).1, vec![u64::MAX]);
+}
+
+#[test]
+fn signed_cells() {
+    let config = Config::new_default();
+    assert_eq!(run_cells::<i8>("-", config, b"", 1).1, vec![-1]);
+    assert_eq!(run_cells::<i8>("-[->+<]", config, b"", 2).1, vec![0, -1]);
+    let mut saturate = config;
+    saturate.overflow = Overflow::Saturate;
+    assert_eq!(
+        run_cells::<i16>("->+<[+>-<]", saturate, b"", 2).1,
+        vec![0, 0]
+    );
+    assert_eq!(run_cells::<i32>("--[+]", saturate, b"", 1).1, vec![0]);
+}
+
+#[test]
+fn io_with_other_cell_types() {
+    let config = Config::new_default();
+    let (output, data) = run_cells::<i8>(",.>,.", config, b"a\xff", 2);
+    assert_eq!(output, b"a\xff");
+    assert_eq!(data, vec![97, -1]);
+    // only the lowest byte of wide cells is output
+    let (output, data) = run_cells::<u32>(",+.>-.", config, b"a", 2);
+    assert_eq!(output, b"b\xff");
+    assert_eq!(data, vec![98, u32::MAX]);
+}
+
+#[test]
+fn big_cells() {
+    let config = Config::new_default();
+    let code = format!("+{}", "[->++<]>[-<+>]<".repeat(10));
+    let (_, data) = run_cells::<BigInt>(&code, config, b"", 2);
+    assert_eq!(data, vec![BigInt::from(1024), BigInt::from(0)]);
+    // doubling 70 times doesn't fit in 64 bits, and is only quick with multiply loops
+    let code = format!("+{}", "[->++<]>[-<+>]<".repeat(70));
+    let source = ::std::rc::Rc::new(::source::File::from_string(code));
+    let mut runtime = fast::Runtime::<BigInt>::new(&::source::lex(source));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.get_cell(0), BigInt::from(1u128 << 70));
+    // more ops are run than the count can hold
+    assert_eq!(runtime.instr_count(), u64::MAX);
+    let (output, data) = run_cells::<BigInt>(".-.,", config, b"\xff", 1);
+    assert_eq!(output, b"\0\xff");
+    assert_eq!(data, vec![BigInt::from(255)]);
+}
+
+#[test]
+fn big_cells_never_wrap() {
+    for overflow in &[Overflow::Wrap, Overflow::Saturate, Overflow::Trap] {
+        let mut config = Config::new_default();
+        config.overflow = *overflow;
+        for code in &["-[+]", "-[-]", "-[->+<]"] {
+            let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+            let tokens = ::source::lex(source);
+            let mut runtime = debug::Runtime::<BigInt>::new();
+            runtime.set_config(config);
+            runtime.add_tokens(&tokens);
+            let mut fast = fast::Runtime::<BigInt>::new(&tokens);
+            fast.set_config(config);
+            let expected = if code == &"-[+]" {
+                Abort::Completed
+            } else {
+                Abort::InstrCapped
+            };
+            assert_eq!(runtime.run(Some(1000), &mut |_| ()), expected, "{:?}", code);
+            assert_eq!(fast.run(Some(1000), &mut |_| ()), expected, "{:?}", code);
+        }
+    }
+}
+
+#[test]
+fn waiting_for_input() {
+    let source = ::std::rc::Rc::new(::source::File::from_string(",>,".to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    runtime.queue_input_str("a");
+    fast.queue_input_str("a");
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::AwaitingInput);
+    assert_eq!(fast.run(None, &mut |_| ()), Abort::AwaitingInput);
+    runtime.queue_input_str("b");
+    fast.queue_input_str("b");
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(fast.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!((runtime.get_cell(0), runtime.get_cell(1)), (97, 98));
+    assert_eq!((fast.get_cell(0), fast.get_cell(1)), (97, 98));
+}
+
+#[test]
+fn eof_unchanged() {
+    let mut test = TestCase::new();
+
+    test.code = ",>,>,";
+    test.input = "a";
+    test.close_input = true;
+    test.initial_data = vec![1, 2, 3];
+    test.expected_data = vec![97, 2, 3];
+    test.expected_ptr = 2;
+
+    test.run();
+}
+
+#[test]
+fn eof_zero() {
+    let mut test = TestCase::new();
+
+    test.code = ",>,>,";
+    test.input = "a";
+    test.close_input = true;
+    test.config.eof = Eof::Zero;
+    test.initial_data = vec![1, 2, 3];
+    test.expected_data = vec![97, 0, 0];
+    test.expected_ptr = 2;
+
+    test.run();
+}
+
+#[test]
+fn eof_minus_one() {
+    let mut test = TestCase::new();
+
+    test.code = ",[-[->+<]],";
+    test.close_input = true;
+    test.config.eof = Eof::MinusOne;
+    test.expected_data = vec![255, 254];
+
+    test.run();
+}
+
+#[test]
+fn eof_minus_one_with_other_cell_types() {
+    let mut config = Config::new_default();
+    config.eof = Eof::MinusOne;
+    assert_eq!(run_cells::<i16>(",", config, b"", 1).1, vec![-1]);
+    assert_eq!(run_cells::<u32>(",", config, b"", 1).1, vec![u32::MAX]);
+    assert_eq!(
+        run_cells::<BigInt>(",", config, b"", 1).1,
+        vec![BigInt::from(-1)]
+    );
+}
+
+#[test]
+fn eof_error() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+,,".to_string()));
+    let mut span = ::source::span::Generator::new(source.clone());
+    let tokens = ::source::lex(source);
+    let mut config = Config::new_default();
+    config.eof = Eof::Error;
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    runtime.queue_input_str("a");
+    runtime.close_input();
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    fast.queue_input_str("a");
+    fast.close_input();
+    let message = "Tried to read past the end of input";
+    let expected = Abort::Error(span.skip(2).span(1).issue(::io::RuntimeError, message));
+    assert_eq!(runtime.run(None, &mut |_| ()), expected);
+    assert_eq!(fast.run(None, &mut |_| ()), expected);
+}
+
+#[test]
+fn utf8_encoding() {
+    let mut config = Config::new_default();
+    config.encoding = Encoding::Utf8;
+    config.eof = Eof::Zero;
+    let text = "h\u{e9}llo \u{20ac}\u{1f600}";
+    let (output, _) = run_cells::<u32>(",[.,]", config, text.as_bytes(), 1);
+    assert_eq!(output, text.as_bytes());
+    let (_, data) = run_cells::<i32>(",>,>,", config, "\u{e9}\u{20ac}\u{1f600}".as_bytes(), 3);
+    assert_eq!(data, vec![0xe9, 0x20ac, 0x1f600]);
+}
+
+#[test]
+fn invalid_utf8() {
+    let mut config = Config::new_default();
+    config.encoding = Encoding::Utf8;
+    // bytes that aren't part of a valid sequence are each read as the replacement char
+    let (_, data) = run_cells::<u16>(",>,>,>,", config, b"\xff\xe2\x82a", 4);
+    assert_eq!(data, vec![0xfffd, 0xfffd, 0xfffd, 97]);
+    let (output, _) = run_cells::<i32>("-.>++++[->+++++<]>[-<<+>>]<<.", config, b"", 0);
+    assert_eq!(output, "\u{fffd}\u{13}".as_bytes());
+    let message = "Input character is too large for a cell";
+    let source = ::std::rc::Rc::new(::source::File::from_string(",".to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = fast::Runtime::<u8>::new(&tokens);
+    runtime.set_config(config);
+    runtime.queue_input_str("\u{20ac}");
+    match runtime.run(None, &mut |_| ()) {
+        Abort::Error(issue) => assert_eq!(issue.message, message),
+        abort => panic!("expected an error, got {:?}", abort),
+    }
+}
+
+#[test]
+fn utf8_waits_for_the_rest_of_a_char() {
+    let mut config = Config::new_default();
+    config.encoding = Encoding::Utf8;
+    let source = ::std::rc::Rc::new(::source::File::from_string(",".to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<u16>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u16>::new(&tokens);
+    fast.set_config(config);
+    runtime.queue_input(&[0xe2, 0x82]);
+    fast.queue_input(&[0xe2, 0x82]);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::AwaitingInput);
+    assert_eq!(fast.run(None, &mut |_| ()), Abort::AwaitingInput);
+    runtime.queue_input(&[0xac]);
+    fast.queue_input(&[0xac]);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(fast.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.get_cell(0), 0x20ac);
+    assert_eq!(fast.get_cell(0), 0x20ac);
+}
+
+#[test]
+fn stream_io() {
+    let source = ::std::rc::Rc::new(::source::File::from_string(",[.,]".to_string()));
+    let tokens = ::source::lex(source);
+    let mut config = Config::new_default();
+    config.eof = Eof::Zero;
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    let mut output = Vec::new();
+    let mut fast_output = Vec::new();
+    let input: &[u8] = b"binary \xff\x01 data";
+    let abort = runtime.run(None, &mut Stream::new(input, &mut output));
+    assert_eq!(abort, Abort::Completed);
+    let abort = fast.run(None, &mut Stream::new(input, &mut fast_output));
+    assert_eq!(abort, Abort::Completed);
+    assert_eq!(output, input);
+    assert_eq!(fast_output, input);
+}
+
+// A reader that gives at most one byte at a time, or a writer, that notes each call in log
+struct Logged<T> {
+    inner: T,
+    log: ::std::rc::Rc<::std::cell::RefCell<String>>,
+}
+
+impl<T: ::std::io::Read> ::std::io::Read for Logged<T> {
+    fn read(&mut self, buffer: &mut [u8]) -> ::std::io::Result<usize> {
+        let len = self.inner.read(&mut buffer[..1])?;
+        let read = String::from_utf8_lossy(&buffer[..len]);
+        self.log.borrow_mut().push_str(&format!("<{}", read));
+        Ok(len)
+    }
+}
+
+impl<T: ::std::io::Write> ::std::io::Write for Logged<T> {
+    fn write(&mut self, bytes: &[u8]) -> ::std::io::Result<usize> {
+        let len = self.inner.write(bytes)?;
+        let written = String::from_utf8_lossy(&bytes[..len]);
+        self.log.borrow_mut().push_str(&format!(">{}", written));
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        self.log.borrow_mut().push('|');
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn stream_flushes_before_reading() {
+    let source = ::std::rc::Rc::new(::source::File::from_string(",.,.+.,.".to_string()));
+    let tokens = ::source::lex(source);
+    let mut config = Config::new_default();
+    config.eof = Eof::Zero;
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    let run = |runtime: &mut dyn FnMut(&mut dyn Io) -> Abort| {
+        let log = ::std::rc::Rc::new(::std::cell::RefCell::new(String::new()));
+        let input = Logged {
+            inner: ::std::io::Cursor::new(b"ab".to_vec()),
+            log: log.clone(),
+        };
+        let mut output = Logged {
+            inner: Vec::new(),
+            log: log.clone(),
+        };
+        assert_eq!(
+            runtime(&mut Stream::new(input, &mut output)),
+            Abort::Completed
+        );
+        assert_eq!(output.inner, b"abc\0");
+        log.take()
+    };
+    // each read waits for the output before it, and the end of input is read once
+    let expected = "|<a>a|<b>b>c|<>\0";
+    assert_eq!(run(&mut |io| runtime.run(None, io)), expected);
+    assert_eq!(run(&mut |io| fast.run(None, io)), expected);
+}
+
+#[test]
+fn callback_io() {
+    let source = ::std::rc::Rc::new(::source::File::from_string(",.,.,.".to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = fast::Runtime::<u8>::new(&tokens);
+    let mut reads = 0;
+    let mut output = Vec::new();
+    // input is only given when it is asked for, and none is ready the third time
+    let abort = runtime.run(
+        None,
+        &mut Callbacks::new(
+            |buffer: &mut [u8]| {
+                reads += 1;
+                if reads > 2 {
+                    return Err(::std::io::ErrorKind::WouldBlock.into());
+                }
+                buffer[0] = b'0' + reads;
+                Ok(1)
+            },
+            |bytes: &[u8]| {
+                output.extend_from_slice(bytes);
+                Ok(())
+            },
+        ),
+    );
+    assert_eq!(abort, Abort::AwaitingInput);
+    assert_eq!(output, b"12");
+    runtime.queue_input_str("3");
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+}
+
+fn fail<T>() -> ::std::io::Result<T> {
+    Err(::std::io::ErrorKind::Other.into())
+}
+
+#[test]
+fn io_errors() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+.,".to_string()));
+    let mut span = ::source::span::Generator::new(source.clone());
+    let tokens = ::source::lex(source);
+    let write_span = span.skip(1).span(1);
+    let read_span = span.span(1);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    for abort in [
+        runtime.run(
+            None,
+            &mut Callbacks::new(|_: &mut [u8]| Ok(0), |_: &[u8]| fail()),
+        ),
+        fast.run(
+            None,
+            &mut Callbacks::new(|_: &mut [u8]| Ok(0), |_: &[u8]| fail()),
+        ),
+    ] {
+        let issue = write_span.issue(::io::RuntimeError, "Failed to write output");
+        assert_eq!(abort, Abort::Error(issue));
+    }
+    for abort in [
+        runtime.run(
+            None,
+            &mut Callbacks::new(|_: &mut [u8]| fail(), |_: &[u8]| Ok(())),
+        ),
+        fast.run(
+            None,
+            &mut Callbacks::new(|_: &mut [u8]| fail(), |_: &[u8]| Ok(())),
+        ),
+    ] {
+        let issue = read_span.issue(::io::RuntimeError, "Failed to read input");
+        assert_eq!(abort, Abort::Error(issue));
+    }
+}
+
+#[test]
+fn errors_point_at_the_op_and_its_loops() {
+    let left = "Pointer moved left of the starting point";
+    let past_end = "Pointer moved past the end of the tape";
+    let past_limit = "Pointer moved past the tape size limit";
+    let mut bounded = Config::new_default();
+    bounded.tape = TapeModel::Bounded(3);
+    let mut limited = Config::new_default();
+    limited.tape_limit = Some(100);
+    let mut small = limited;
+    small.tape_limit = Some(3);
+    let mut wrapping = small;
+    wrapping.tape = TapeModel::Wrapping(5);
+    let mut infinite = small;
+    infinite.tape = TapeModel::Infinite;
+    infinite.tape_limit = Some(2);
+    // runs code that fails at the op at byte at, which is in the loops that start at loops
+    let check = |code: &str, config: Config, data: &[u8], at: usize, loops: &[usize], message| {
+        let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+        let mut s = ::source::span::Generator::new(source);
+        let mut expected = s.jump_to(at).span(1).issue(::io::RuntimeError, message);
+        for start in loops {
+            expected = expected.with_note(s.jump_to(*start).span(1), "In this loop");
+        }
+        let (a, b) = match run_aborts(code, config, data, 0) {
+            (Abort::Error(a), Abort::Error(b)) | (Abort::TapeFull(a), Abort::TapeFull(b)) => (a, b),
+            (a, b) => panic!("expected errors from {:?}, got {:?} and {:?}", code, a, b),
+        };
+        assert_eq!(a, expected, "debug runtime {:?}", code);
+        assert_eq!(b, expected, "fast runtime {:?}", code);
+    };
+    check("+[>+[<<.]]", Config::new_default(), &[], 6, &[4, 1], left);
+    check("+++<+", Config::new_default(), &[], 3, &[], left);
+    check(">>>+", bounded, &[], 2, &[], past_end);
+    check("+[>+]", bounded, &[], 2, &[1], past_end);
+    check("+[>+]", limited, &[], 2, &[1], past_limit);
+    // loops that are run as a multiply or a scan
+    check(">>[->+<]", bounded, &[0, 0, 1], 4, &[2], past_end);
+    check("[-<+>]", bounded, &[1], 2, &[0], left);
+    check("[<]", bounded, &[1, 1, 1], 1, &[0], left);
+    check("[>>]", small, &[1, 1, 1], 1, &[0], past_limit);
+    check("[<<]", wrapping, &[1], 1, &[0], past_limit);
+    check("><<+>>", infinite, &[], 5, &[], past_limit);
+}
+
+#[test]
+fn extraneous_closing_brace() {
+    let code = "+[-]]";
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let mut s = ::source::span::Generator::new(source);
+    let expected = s
+        .skip(4)
+        .span(1)
+        .issue(::io::RuntimeError, "Extraneous closing brace");
+    let (a, b) = run_errors(code, Config::new_default(), &[], 0);
+    assert_eq!(a, expected);
+    assert_eq!(b, expected);
+}
+
+#[test]
+fn breakpoints() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+++[\n>+<-]".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    runtime.add_breakpoint(0, 2);
+    runtime.add_breakpoint(1, 1);
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Breakpoint(s.skip(2).span(1)));
+    assert_eq!(runtime.get_cell(0), 2);
+    assert!(runtime.remove_breakpoint(0, 2));
+    assert!(!runtime.remove_breakpoint(0, 2));
+    // the loop stops at the breakpoint every time around
+    let span = s.jump_to(6).span(1);
+    for i in 0..3 {
+        assert_eq!(
+            runtime.run(None, &mut |_| ()),
+            Abort::Breakpoint(span.clone())
+        );
+        assert!(runtime.at_breakpoint());
+        assert_eq!(runtime.get_cell(1), i);
+    }
+    assert!(runtime.remove_breakpoint(1, 1));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!((runtime.get_cell(0), runtime.get_cell(1)), (0, 3));
+}
+
+#[test]
+fn debug_op() {
+    let mut config = Config::new_default();
+    config.debug_op = true;
+    let source = ::std::rc::Rc::new(::source::File::from_string("++[>+#<-]#".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&tokens);
+    let mut fast = fast::Runtime::<u8>::new(&tokens);
+    fast.set_config(config);
+    let in_loop = s.skip(5).span(1);
+    let at_end = s.skip(3).span(1);
+    for (ptr, cell, span) in [(1, 1, &in_loop), (1, 2, &in_loop), (0, 2, &at_end)] {
+        assert_eq!(
+            runtime.run(None, &mut |_| ()),
+            Abort::Breakpoint(span.clone())
+        );
+        assert_eq!(fast.run(None, &mut |_| ()), Abort::Breakpoint(span.clone()));
+        assert_eq!((runtime.get_ptr(), runtime.get_cell(1)), (ptr, cell));
+        assert_eq!((fast.get_ptr(), fast.get_cell(1)), (ptr, cell));
+    }
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(fast.run(None, &mut |_| ()), Abort::Completed);
+}
+
+#[test]
+fn debug_op_is_ignored_unless_enabled() {
+    let mut test = TestCase::new();
+
+    test.code = "++[>+#<-]#";
+    test.expected_data = vec![0, 2];
+
+    test.run();
+}
+
+#[test]
+fn stopping_at_the_first_op() {
+    let mut config = Config::new_default();
+    config.debug_op = true;
+    let source = ::std::rc::Rc::new(::source::File::from_string("#+#".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let tokens = ::source::lex(source);
+    let first = s.span(1);
+    let last = s.skip(1).span(1);
+    let stops = [
+        (None, Abort::Breakpoint(first.clone())),
+        (None, Abort::Breakpoint(last.clone())),
+        (None, Abort::Completed),
+    ];
+    // a run of one op stops at a '#' it gets to, and the next one runs it
+    let steps = [
+        (Some(1), Abort::Breakpoint(first)),
+        (Some(1), Abort::InstrCapped),
+        (Some(1), Abort::InstrCapped),
+        (Some(1), Abort::Breakpoint(last)),
+        (Some(1), Abort::InstrCapped),
+        (Some(1), Abort::Completed),
+    ];
+    for runs in [&stops[..], &steps[..]] {
+        let mut runtime = debug::Runtime::<u8>::new();
+        runtime.set_config(config);
+        runtime.add_tokens(&tokens);
+        let mut fast = fast::Runtime::<u8>::new(&tokens);
+        fast.set_config(config);
+        for (instr_cap, abort) in runs {
+            assert_eq!(runtime.run(*instr_cap, &mut |_| ()), *abort);
+            assert_eq!(fast.run(*instr_cap, &mut |_| ()), *abort);
+        }
+        assert_eq!(runtime.get_cell(0), 1);
+        assert_eq!(fast.get_cell(0), 1);
+    }
+    // a breakpoint on the first op stops the code before it is run
+    let mut runtime = debug_runtime::<u8>("+", Config::new_default());
+    runtime.add_breakpoint(0, 0);
+    match runtime.run(None, &mut |_| ()) {
+        Abort::Breakpoint(span) => assert_eq!((span.line(), span.col()), (0, 0)),
+        abort => panic!("expected a breakpoint, got {:?}", abort),
+    }
+    assert_eq!(runtime.get_cell(0), 0);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+}
+
+#[test]
+fn debug_op_is_not_counted_unless_enabled() {
+    let mut runtime = debug_runtime::<u8>("#+#+#", Config::new_default());
+    assert_eq!(runtime.run(Some(1), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(runtime.run(Some(1), &mut |_| ()), Abort::InstrCapped);
+    assert_eq!(runtime.get_cell(0), 2);
+    assert_eq!(runtime.run(Some(1), &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.instr_count(), 2);
+}
+
+#[test]
+fn watchpoints() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+>+<[->-+<]>>+".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    runtime.add_watchpoint(1);
+    runtime.add_watchpoint(2);
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.skip(2).span(1)));
+    assert_eq!(runtime.get_cell(1), 1);
+    // '-' then '+' on a cell changes it twice, but it ends up the same
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.jump_to(7).span(1)));
+    assert_eq!(runtime.get_cell(1), 0);
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.jump_to(8).span(1)));
+    assert!(runtime.remove_watchpoint(1));
+    assert!(!runtime.remove_watchpoint(1));
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.jump_to(13).span(1)));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.instr_count(), 14);
+}
+
+#[test]
+fn conditions() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+++[>++<-]".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    let condition = Condition::parse("ptr == 1 and cell[ptr] > 2").unwrap();
+    runtime.add_condition(condition.clone());
+    runtime.add_condition(Condition::parse("instrs == 2").unwrap());
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.skip(1).span(1)));
+    assert_eq!(runtime.instr_count(), 2);
+    // each condition only stops the code when it becomes true, not while it stays true
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.jump_to(5).span(1)));
+    assert_eq!((runtime.get_ptr(), runtime.get_cell(1)), (1, 3));
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.jump_to(4).span(1)));
+    assert_eq!((runtime.get_ptr(), runtime.get_cell(1)), (1, 4));
+    assert!(runtime.remove_condition(&condition));
+    assert!(!runtime.remove_condition(&condition));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+}
+
+#[test]
+fn conditions_that_are_already_met() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+>+<-->".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    runtime.set_cell(0, 5);
+    // both are true to begin with, so they only stop the code once they become true again
+    runtime.add_condition(Condition::parse("cell[0] > 2").unwrap());
+    runtime.add_condition(Condition::parse("ptr == 0").unwrap());
+    let abort = runtime.run(None, &mut |_| ());
+    assert_eq!(abort, Abort::Watchpoint(s.skip(3).span(1)));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+}
+
+// the pointer, the first few cells, the next op and how many ops have been run
+fn debug_state(runtime: &debug::Runtime<u8>) -> (isize, Vec<u8>, Option<usize>, u64) {
+    (
+        runtime.get_ptr(),
+        (0..6).map(|i| runtime.get_cell(i)).collect(),
+        runtime.next_span().map(|span| span.start_byte()),
+        runtime.instr_count(),
+    )
+}
+
+#[test]
+fn running_backwards() {
+    let code = ">,[>,]<[[->+<]<]>>.";
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    runtime.set_history_limit(1 << 20);
+    runtime.queue_input(&[1, 2, 3, 0]);
+    let mut output = Vec::new();
+    let mut states = vec![debug_state(&runtime)];
+    while runtime.run(Some(1), &mut |b| output.push(b)) == Abort::InstrCapped {
+        states.push(debug_state(&runtime));
+    }
+    while let Some(state) = states.pop() {
+        assert_eq!(debug_state(&runtime), state);
+        runtime.run_back(Some(1));
+    }
+    assert_eq!(runtime.run_back(None), Abort::StartOfHistory);
+    // the input that was read is read again
+    let mut again = Vec::new();
+    assert_eq!(runtime.run(None, &mut |b| again.push(b)), Abort::Completed);
+    assert_eq!((output, again), (vec![1], vec![1]));
+}
+
+#[test]
+fn running_backwards_stops_where_running_forwards_would() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+++[>+<-]>+".to_string()));
+    let mut s = ::source::span::Generator::new(source.clone());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    runtime.set_history_limit(1 << 20);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    runtime.add_breakpoint(0, 4);
+    let span = s.skip(4).span(1);
+    for cell in &[2, 1, 0] {
+        assert_eq!(runtime.run_back(None), Abort::Breakpoint(span.clone()));
+        assert_eq!(runtime.get_cell(1), *cell);
+    }
+    assert!(runtime.remove_breakpoint(0, 4));
+    runtime.add_watchpoint(0);
+    let span = s.jump_to(2).span(1);
+    assert_eq!(runtime.run_back(None), Abort::Watchpoint(span.clone()));
+    assert_eq!(runtime.get_cell(0), 3);
+    assert_eq!(runtime.run_back(Some(1)), Abort::InstrCapped);
+    assert_eq!(runtime.get_cell(0), 2);
+    // undoing one op at a time gets back to where the '+' before would stop
+    assert_eq!(
+        runtime.run_back(Some(1)),
+        Abort::Watchpoint(s.jump_to(1).span(1))
+    );
+    assert_eq!(runtime.get_cell(0), 2);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Watchpoint(span));
+    assert!(runtime.remove_watchpoint(0));
+    runtime.add_condition(Condition::parse("cell[1] == 2").unwrap());
+    let span = s.jump_to(5).span(1);
+    assert_eq!(
+        runtime.run(None, &mut |_| ()),
+        Abort::Watchpoint(span.clone())
+    );
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.run_back(None), Abort::Watchpoint(span));
+    assert_eq!(runtime.get_cell(1), 2);
+}
+
+#[test]
+fn history_limit() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+".repeat(100)));
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&::source::lex(source));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.run_back(None), Abort::StartOfHistory);
+    assert_eq!(runtime.get_cell(0), 100);
+    runtime.set_history_limit(1 << 20);
+    let source = ::std::rc::Rc::new(::source::File::from_string("+".repeat(100)));
+    runtime.add_tokens(&::source::lex(source));
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    runtime.set_history_limit(1000);
+    assert_eq!(runtime.run_back(None), Abort::StartOfHistory);
+    let undone = 200 - runtime.get_cell(0) as usize;
+    assert!(undone > 0 && undone < 100);
+    assert_eq!(runtime.run_back(Some(0)), Abort::InstrCapped);
+    assert_eq!(runtime.get_cell(0), 200 - undone as u8);
+}
+
+#[test]
+fn history_limit_counts_big_cells() {
+    let source = ::std::rc::Rc::new(::source::File::from_string("+".repeat(5)));
+    let mut runtime = debug::Runtime::<BigInt>::new();
+    runtime.add_tokens(&::source::lex(source));
+    let big = BigInt::from(1) << 8000usize;
+    runtime.set_cell(0, big.clone());
+    // each step remembers a cell of 1000 bytes, which is too big to keep any of them
+    runtime.set_history_limit(1000);
+    assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    assert_eq!(runtime.run_back(None), Abort::StartOfHistory);
+    assert_eq!(runtime.get_cell(0), big + 5);
+}
+
+fn debug_runtime<D: Cell>(code: &str, config: Config) -> debug::Runtime<D> {
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let mut runtime = debug::Runtime::<D>::new();
+    runtime.set_config(config);
+    runtime.add_tokens(&::source::lex(source));
+    runtime
+}
+
+#[test]
+fn snapshots() {
+    let code = "<-<+>>,[[->+<]>,]";
+    let mut config = Config::new_default();
+    config.tape = TapeModel::Infinite;
+    config.eof = Eof::Zero;
+    let start = |cap| {
+        let mut runtime = debug_runtime::<i16>(code, config);
+        runtime.queue_input_str("ab");
+        runtime.close_input();
+        assert_eq!(runtime.run(cap, &mut |_| ()), Abort::InstrCapped);
+        runtime
+    };
+    let mut runtime = start(Some(12));
+    let mut snapshot = Vec::new();
+    runtime.save_snapshot(&mut snapshot).unwrap();
+    assert_eq!(
+        String::from_utf8(snapshot.clone()).unwrap(),
+        "bft snapshot 1\n\
+         code 88fe39e25896eeaf\n\
+         ptr 1\n\
+         stack 7 8 12\n\
+         cells -2 1 -1 96 1\n\
+         input 62\n\
+         closed true\n\
+         instrs 12\n"
+    );
+    let mut loaded = debug_runtime::<i16>(code, config);
+    loaded.load_snapshot(&snapshot[..]).unwrap();
+    let mut reference = start(Some(1));
+    for runtime in &mut [&mut runtime, &mut loaded, &mut reference] {
+        assert_eq!(runtime.run(None, &mut |_| ()), Abort::Completed);
+    }
+    let state = |runtime: &debug::Runtime<i16>| {
+        let cells: Vec<i16> = (-2..4).map(|i| runtime.get_cell(i)).collect();
+        (cells, runtime.get_ptr(), runtime.instr_count())
+    };
+    assert_eq!(state(&runtime), state(&reference));
+    assert_eq!(state(&loaded), state(&reference));
+}
+
+#[test]
+fn snapshot_of_different_code() {
+    let mut runtime = debug_runtime::<u8>("+[>+<-]", Config::new_default());
+    let mut snapshot = Vec::new();
+    runtime.save_snapshot(&mut snapshot).unwrap();
+    // comments are not part of the code
+    let mut same = debug_runtime::<u8>("+ [>+<-] set cell 1", Config::new_default());
+    assert_eq!(same.load_snapshot(&snapshot[..]), Ok(()));
+    let mut different = debug_runtime::<u8>("+[>++<-]", Config::new_default());
+    assert_eq!(
+        different.load_snapshot(&snapshot[..]).unwrap_err().message,
+        "Snapshot was taken while running different code"
+    );
+    runtime.set_cell(0, 7);
+    assert_eq!(
+        runtime
+            .load_snapshot(&b"bft snapshot 0\n"[..])
+            .unwrap_err()
+            .message,
+        "Snapshot is not valid: it should start with 'bft snapshot 1'"
+    );
+    let text = String::from_utf8(snapshot).unwrap();
+    for (from, to, message) in [
+        ("ptr 0", "ptr", "'ptr' should have one value"),
+        ("ptr 0", "pointer 0", "expected a 'ptr' line"),
+        (
+            "input ",
+            "input 6",
+            "'input' should be a string of hex bytes",
+        ),
+        (
+            "cells 0",
+            "cells 0 1000",
+            "'1000' in 'cells' is not a valid value",
+        ),
+    ] {
+        let broken = text.replace(from, to);
+        assert_eq!(
+            runtime
+                .load_snapshot(broken.as_bytes())
+                .unwrap_err()
+                .message,
+            format!("Snapshot is not valid: {}", message)
+        );
+    }
+    let broken = text.replace("stack 0", "stack 0 2");
+    assert_eq!(
+        runtime
+            .load_snapshot(broken.as_bytes())
+            .unwrap_err()
+            .message,
+        "Snapshot's loops do not match the code"
+    );
+    let mut config = Config::new_default();
+    for (tape, ptr) in [
+        (TapeModel::RightInfinite, "-1"),
+        (TapeModel::Bounded(3), "3"),
+        (TapeModel::Wrapping(3), "4"),
+    ] {
+        config.tape = tape;
+        runtime.set_config(config);
+        let broken = text.replace("ptr 0", &format!("ptr {}", ptr));
+        assert_eq!(
+            runtime
+                .load_snapshot(broken.as_bytes())
+                .unwrap_err()
+                .message,
+            "Snapshot's pointer is not on the tape"
+        );
+    }
+    assert_eq!(runtime.get_cell(0), 7);
+}
+
+#[test]
+fn tape_limit_after_loading_a_snapshot() {
+    let mut config = Config::new_default();
+    config.tape_limit = Some(3);
+    let mut runtime = debug_runtime::<u8>(">>>>>>", config);
+    let mut snapshot = Vec::new();
+    runtime.save_snapshot(&mut snapshot).unwrap();
+    runtime.load_snapshot(&snapshot[..]).unwrap();
+    match runtime.run(None, &mut |_| ()) {
+        Abort::TapeFull(issue) => {
+            assert_eq!(issue.message, "Pointer moved past the tape size limit")
+        }
+        abort => panic!("expected a full tape, got {:?}", abort),
+    }
+}
+
+#[test]
+fn conditions_met_in_a_snapshot() {
+    let mut runtime = debug_runtime::<u8>("+++>>", Config::new_default());
+    assert_eq!(runtime.run(Some(3), &mut |_| ()), Abort::InstrCapped);
+    let mut snapshot = Vec::new();
+    runtime.save_snapshot(&mut snapshot).unwrap();
+    let mut loaded = debug_runtime::<u8>("+++>>", Config::new_default());
+    loaded.add_condition(Condition::parse("cell[0] == 3").unwrap());
+    loaded.load_snapshot(&snapshot[..]).unwrap();
+    // the condition was already met when the snapshot was taken
+    assert_eq!(loaded.run(None, &mut |_| ()), Abort::Completed);
+}
+
+fn trace(tracer: Tracer<Vec<u8>>) -> String {
+    let mut runtime = debug_runtime::<u8>(",+\n[->+<]", Config::new_default());
+    runtime.queue_input(&[1]);
+    let mut tracer = tracer;
+    assert_eq!(
+        runtime.run_with(None, &mut |_| (), &mut tracer),
+        Abort::Completed
+    );
+    String::from_utf8(tracer.finish().unwrap()).unwrap()
+}
+
+#[test]
+fn tracing() {
+    assert_eq!(
+        trace(Tracer::new(Vec::new())),
+        "0 0 , 0:0 0 0 1\n\
+         1 1 + 0:1 0 1 2\n\
+         2 2 [ 1:0 0 2 2\n\
+         3 3 - 1:1 0 2 1\n\
+         4 4 > 1:2 0 1 1\n\
+         5 5 + 1:3 1 0 1\n\
+         6 6 < 1:4 1 1 1\n\
+         7 7 ] 1:5 0 1 1\n\
+         8 3 - 1:1 0 1 0\n\
+         9 4 > 1:2 0 0 0\n\
+         10 5 + 1:3 1 1 2\n\
+         11 6 < 1:4 1 2 2\n\
+         12 7 ] 1:5 0 0 0\n"
+    );
+    assert_eq!(
+        trace(Tracer::new(Vec::new()).with_lines(0, 0)),
+        "0 0 , 0:0 0 0 1\n1 1 + 0:1 0 1 2\n"
+    );
+    assert_eq!(
+        trace(Tracer::new(Vec::new()).with_start(11)),
+        "11 6 < 1:4 1 2 2\n12 7 ] 1:5 0 0 0\n"
+    );
+    assert_eq!(
+        trace(Tracer::new(Vec::new()).with_lines(0, 0).with_start(2)),
+        ""
+    );
+}
+
+#[test]
+fn profiling() {
+    let code = "++[>+++[>+<-]<-]\n# comment\n>>.<<[]\n";
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let tokens = ::source::lex(source.clone());
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&tokens);
+    let mut profiler = Profiler::new(&tokens, &Config::new_default());
+    assert_eq!(
+        runtime.run_with(None, &mut |_| (), &mut profiler),
+        Abort::Completed
+    );
+    assert_eq!(profiler.count(0), 1);
+    assert_eq!(profiler.count(8), 6);
+    assert_eq!(profiler.count(100), 0);
+    let mut span = ::source::span::Generator::new(source.clone());
+    assert_eq!(
+        profiler.hottest_loops(),
+        vec![
+            LoopProfile {
+                start: span.jump_to(2).span(1),
+                end: span.jump_to(15).span(1),
+                entered: 1,
+                iterations: 2,
+                ops: 47,
+            },
+            LoopProfile {
+                start: span.jump_to(7).span(1),
+                end: span.jump_to(12).span(1),
+                entered: 2,
+                iterations: 6,
+                ops: 32,
+            },
+            LoopProfile {
+                start: span.jump_to(32).span(1),
+                end: span.jump_to(33).span(1),
+                entered: 1,
+                iterations: 0,
+                ops: 1,
+            },
+        ]
+    );
+    assert_eq!(
+        profiler.annotate(&source),
+        "49 | ++[>+++[>+<-]<-]\n   | # comment\n 6 | >>.<<[]\n"
+    );
+    // with --debug-op, '#' is an op that wasn't run
+    let mut config = Config::new_default();
+    config.debug_op = true;
+    assert_eq!(
+        Profiler::new(&tokens, &config).annotate(&source),
+        "0 | ++[>+++[>+<-]<-]\n0 | # comment\n0 | >>.<<[]\n"
+    );
+}
+
+fn coverage(input: &[u8]) -> LcovRecord {
+    let code = ",[>+<-]\n# comment\n>[.[-]]";
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let tokens = ::source::lex(source);
+    let mut runtime = debug::Runtime::<u8>::new();
+    runtime.add_tokens(&tokens);
+    runtime.queue_input(input);
+    runtime.close_input();
+    let mut coverage = Coverage::new(&tokens, &Config::new_default());
+    assert_eq!(
+        runtime.run_with(None, &mut |_| (), &mut coverage),
+        Abort::Completed
+    );
+    coverage.lcov("test.b")
+}
+
+#[test]
+fn lcov_coverage() {
+    let mut record = coverage(b"");
+    let mut lcov = Vec::new();
+    record.write(&mut lcov).unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "TN:\n\
+         SF:test.b\n\
+         BRDA:1,1,0,0\n\
+         BRDA:1,1,1,1\n\
+         BRDA:3,1,0,0\n\
+         BRDA:3,1,1,1\n\
+         BRDA:3,3,0,-\n\
+         BRDA:3,3,1,-\n\
+         BRF:6\n\
+         BRH:2\n\
+         DA:1,1\n\
+         DA:3,1\n\
+         LF:2\n\
+         LH:2\n\
+         end_of_record\n"
+    );
+    // with --debug-op, '#' is an op that wasn't run
+    let mut config = Config::new_default();
+    config.debug_op = true;
+    let source = ::std::rc::Rc::new(::source::File::from_string("+\n#".to_string()));
+    let lines = Coverage::new(&::source::lex(source), &config)
+        .lcov("test.b")
+        .lines;
+    assert_eq!(lines.into_iter().collect::<Vec<_>>(), vec![(1, 0), (2, 0)]);
+    record.merge(&coverage(b"\x02"));
+    assert_eq!(record.lines.values().collect::<Vec<_>>(), vec![&3, &3]);
+    assert_eq!(
+        record.branches.values().collect::<Vec<_>>(),
+        vec![&Some(1), &Some(1), &Some(1), &Some(1), &Some(1), &Some(0)]
+    );
+}
+
+#[test]
+fn reading_lcov() {
+    let mut lcov = b"TN:\n".to_vec();
+    coverage(b"\x02").write(&mut lcov).unwrap();
+    let mut other = LcovRecord::new("other.b");
+    other.lines.insert(3, 0);
+    other.write(&mut lcov).unwrap();
+    let text = String::from_utf8(lcov).unwrap();
+    assert_eq!(
+        LcovRecord::read_all(&text),
+        Ok(vec![coverage(b"\x02"), other])
+    );
+    // anything that isn't a line or branch is left out, and checksums are ignored
+    let record = LcovRecord::read_all("SF:a.b\nFN:1,f\nDA:2,3,dGVzdA==\nend_of_record\n").unwrap();
+    assert_eq!(record[0].lines.get(&2), Some(&3));
+    for (text, message) in [
+        ("DA:1,1\n", "line 1: expected 'SF' to start a record"),
+        ("SF:a.b\nDA:1\n", "line 2: 'DA:1' should have 2 numbers"),
+        (
+            "SF:a.b\nBRDA:1,x,0,1\n",
+            "line 2: 'BRDA:1,x,0,1' should have 4 numbers",
+        ),
+        (
+            "SF:a.b\nSF:c.b\n",
+            "line 2: expected 'end_of_record' before 'SF'",
+        ),
+        (
+            "SF:a.b\nDA:1,1\n",
+            "line 2: expected 'end_of_record' at the end",
+        ),
+    ] {
+        assert_eq!(
+            LcovRecord::read_all(text).unwrap_err().message,
+            format!("Coverage is not valid lcov: {}", message)
+        );
+    }
+}
+
+fn detect_loops(code: &str, config: Config, data: &[u8]) -> Abort {
+    let mut runtime = debug_runtime::<u8>(code, config);
+    for (i, value) in data.iter().enumerate() {
+        runtime.set_cell(i as isize, *value);
+    }
+    runtime.set_loop_detection(true);
+    runtime.close_input();
+    runtime.run(Some(10000), &mut |_| ())
+}
+
+#[test]
+fn loops_that_never_end() {
+    let never_ends = "Loop never ends, as an iteration got back to the state it started in";
+    for code in &[
+        "+[]",
+        "+[>+<]",
+        "+[+-]",
+        "+>+[<[>]<]",
+        "+[[-]+]",
+        "+[>[-]<]",
+    ] {
+        match detect_loops(code, Config::new_default(), &[]) {
+            Abort::Error(issue) => assert_eq!(issue.message, never_ends, "{}", code),
+            abort => panic!("expected {} to never end, got {:?}", code, abort),
+        }
+    }
+    // loops that end, or that do input or output
+    for code in &["++[>+++[>+<-]<-]", "+[>]", "+[-+-]", "+[.]", "+[>,<]"] {
+        let abort = detect_loops(code, Config::new_default(), &[]);
+        assert!(
+            abort == Abort::Completed || abort == Abort::InstrCapped,
+            "{} stopped with {:?}",
+            code,
+            abort
+        );
+    }
+    // a breakpoint counts as output, so stopping at it again and again isn't a loop never ending
+    let mut config = Config::new_default();
+    config.debug_op = true;
+    let mut runtime = debug_runtime::<u8>("+[#]", config);
+    runtime.set_loop_detection(true);
+    for _ in 0..3 {
+        match runtime.run(Some(10000), &mut |_| ()) {
+            Abort::Breakpoint(_) => (),
+            abort => panic!("expected a breakpoint, got {:?}", abort),
+        }
+    }
+    // a cell that isn't tested can still end the loop with an error
+    let mut config = Config::new_default();
+    config.overflow = Overflow::Trap;
+    match detect_loops("+[>+<]", config, &[]) {
+        Abort::Error(issue) => assert_eq!(issue.message, "Cell overflowed"),
+        abort => panic!("expected an overflow, got {:?}", abort),
+    }
+    match detect_loops("+[]", config, &[]) {
+        Abort::Error(issue) => assert_eq!(issue.message, never_ends),
+        abort => panic!("expected +[] to never end, got {:?}", abort),
+    }
+}
+
+#[test]
+fn loop_detection_points_at_the_loop() {
+    let code = "+[>+[<+>]]";
+    let source = ::std::rc::Rc::new(::source::File::from_string(code.to_string()));
+    let mut s = ::source::span::Generator::new(source);
+    let expected = s
+        .skip(4)
+        .span(5)
+        .issue(
+            ::io::RuntimeError,
+            "Loop never ends, as an iteration got back to the state it started in",
+        )
+        .with_note(s.jump_to(1).span(1), "In this loop");
+    assert_eq!(
+        detect_loops(code, Config::new_default(), &[]),
+        Abort::Error(expected)
+    );
+    // it is off by default
+    let mut runtime = debug_runtime::<u8>(code, Config::new_default());
+    assert_eq!(runtime.run(Some(10000), &mut |_| ()), Abort::InstrCapped);
+}
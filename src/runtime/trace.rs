@@ -0,0 +1,76 @@
+use super::*;
+use std::io;
+use std::io::Write;
+
+/// Writes a line for each op run by debug::Runtime::run_with, as it is run:
+///
+/// `COUNT INDEX OP LINE:COL PTR BEFORE AFTER`
+///
+/// COUNT is the number of ops run before it, INDEX is where the op is in the code, PTR is the
+/// pointer before the op is run, and BEFORE and AFTER are the values of the cell at PTR before and
+/// after it is run. Lines and columns count from 0. Output should be buffered, as traces are large.
+pub struct Tracer<W> {
+    output: W,
+    lines: Option<(u32, u32)>, // the first and last line ops are traced on
+    start: u64,                // the number of ops run before any are traced
+    error: Option<io::Error>,  // the first error writing output, after which nothing is written
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(output: W) -> Tracer<W> {
+        Tracer {
+            output,
+            lines: None,
+            start: 0,
+            error: None,
+        }
+    }
+
+    /// Only traces ops from the first line to the last line, including the last line
+    pub fn with_lines(mut self, first: u32, last: u32) -> Tracer<W> {
+        self.lines = Some((first, last));
+        self
+    }
+
+    /// Only traces ops once count ops have been run
+    pub fn with_start(mut self, count: u64) -> Tracer<W> {
+        self.start = count;
+        self
+    }
+
+    /// Flushes the output and returns it, or the first error writing to it
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => self.output.flush().map(|_| self.output),
+        }
+    }
+}
+
+impl<D: Cell, W: Write> Observer<D> for Tracer<W> {
+    fn op_run(&mut self, event: &Event<D>) {
+        let line = event.span.line();
+        let in_lines = match self.lines {
+            Some((first, last)) => line >= first && line <= last,
+            None => true,
+        };
+        if self.error.is_some() || event.count < self.start || !in_lines {
+            return;
+        }
+        let written = writeln!(
+            self.output,
+            "{} {} {} {}:{} {} {} {}",
+            event.count,
+            event.instr,
+            event.op,
+            line,
+            event.span.col(),
+            event.ptr,
+            event.before,
+            event.after
+        );
+        if let Err(e) = written {
+            self.error = Some(e);
+        }
+    }
+}
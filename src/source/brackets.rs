@@ -0,0 +1,105 @@
+use io;
+use io::Issue;
+use runtime::Op;
+use source::Span;
+use source::Token;
+
+/// Finds every bracket that does not have a match, in the order they are in the code
+pub fn check_brackets(tokens: &[Token]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut open: Vec<&Span> = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Bf(Op::Start, span) => open.push(span),
+            Token::Bf(Op::End, span) if open.pop().is_none() => {
+                issues.push(span.issue(io::Error, "Unmatched closing bracket"));
+            }
+            _ => (),
+        }
+    }
+    for span in open {
+        issues.push(span.issue(io::Error, "Unclosed bracket").with_note(
+            Span::at_end_of(span.src().clone()),
+            "Expected ']' before end of file",
+        ));
+    }
+    issues.sort_by_key(|issue| issue.span.as_ref().map(|span| span.start_byte()));
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use source::lex;
+    use source::span;
+    use source::File;
+    use std::rc::Rc;
+
+    fn load(source: &str) -> (span::Generator, Vec<Issue>) {
+        let source = Rc::new(File::from_string(source.to_string()));
+        let span = span::Generator::new(source.clone());
+        let issues = check_brackets(&lex(source));
+        (span, issues)
+    }
+
+    #[test]
+    fn balanced() {
+        let (_, issues) = load("+[->[-]<]{[]}");
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn unmatched_close() {
+        let (mut s, issues) = load("[]]+]");
+        assert_eq!(
+            issues,
+            vec![
+                s.skip(2)
+                    .span(1)
+                    .issue(io::Error, "Unmatched closing bracket"),
+                s.skip(1)
+                    .span(1)
+                    .issue(io::Error, "Unmatched closing bracket"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_open() {
+        let (mut s, issues) = load("[[]\n[-");
+        assert_eq!(
+            issues,
+            vec![
+                s.span(1)
+                    .issue(io::Error, "Unclosed bracket")
+                    .with_note(s.jump_to(6).span(0), "Expected ']' before end of file"),
+                s.jump_to(4)
+                    .span(1)
+                    .issue(io::Error, "Unclosed bracket")
+                    .with_note(s.jump_to(6).span(0), "Expected ']' before end of file"),
+            ]
+        );
+    }
+
+    #[test]
+    fn position_on_later_line() {
+        let (_, issues) = load("+[\n]]");
+        assert_eq!(issues.len(), 1);
+        let span = issues[0].span.clone().unwrap();
+        assert_eq!((span.line(), span.col(), span.width()), (1, 1, 1));
+    }
+
+    #[test]
+    fn mixed() {
+        let (mut s, issues) = load("][");
+        assert_eq!(
+            issues,
+            vec![
+                s.span(1).issue(io::Error, "Unmatched closing bracket"),
+                s.span(1)
+                    .issue(io::Error, "Unclosed bracket")
+                    .with_note(s.span(0), "Expected ']' before end of file"),
+            ]
+        );
+    }
+}
@@ -1,42 +1,49 @@
-use io;
-
 use std;
 use std::fmt;
 use std::io::Read;
 
+/// Source code, and where it came from
 #[derive(Debug, PartialEq)]
 pub struct File {
-    pub path: Option<String>,
-    pub contents: String,
+    path: Option<String>,
+    contents: String,
 }
 
 impl File {
-    pub fn open(path: &str, options: &io::Options) -> Result<File, String> {
-        //options.debug(&format!("Reading {}", path));
-        let mut f = match std::fs::File::open(path.clone()) {
+    pub fn open(path: &str) -> Result<File, String> {
+        let mut f = match std::fs::File::open(path) {
             Result::Ok(v) => v,
-            Result::Err(e) => return Err(format!("'{}': {}", path, e.to_string())),
+            Result::Err(e) => return Err(format!("'{}': {}", path, e)),
         };
         let mut contents = String::new();
         match f.read_to_string(&mut contents) {
             Result::Ok(_) => (),
-            Result::Err(e) => return Err(format!("'{}': {}", path, e.to_string())),
+            Result::Err(e) => return Err(format!("'{}': {}", path, e)),
         }
         Ok(File {
             path: Some(path.to_string()),
-            contents: contents,
+            contents,
         })
     }
 
     pub fn from_string(contents: String) -> File {
         File {
             path: None,
-            contents: contents,
+            contents,
         }
     }
 
+    /// The path the file was opened from, or None if it was not loaded from disk
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
     pub fn unwrap_path(&self) -> String {
-        self.path.clone().unwrap_or("[UNKNOWN]".to_string())
+        self.path.clone().unwrap_or_else(|| "[UNKNOWN]".to_string())
     }
 }
 
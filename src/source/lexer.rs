@@ -10,15 +10,15 @@ type TokenIter = Span;
 impl TokenIter {
     fn char_indices<'a>(&'a self) -> (usize, CharIndices<'a>) {
         (
-            self.end_byte,
-            self.src.contents[self.end_byte..].char_indices(),
+            self.end_byte(),
+            self.src().contents()[self.end_byte()..].char_indices(),
         )
     }
 
     fn span_to(&self, offset: usize, mut end: CharIndices) -> Span {
         self.span_to_byte(match end.next() {
             Some((i, _)) => offset + i,
-            None => self.src.contents.len(),
+            None => self.src().contents().len(),
         })
     }
 
@@ -56,9 +56,9 @@ impl TokenIter {
         let mut ident = None;
         while let Some((_, c)) = chars.next() {
             match c {
-                '0'...'9' | 'a'...'z' | 'A'...'Z' | '_' => {
+                '0'..='9' | 'a'..='z' | 'A'..='Z' | '_' => {
                     ident = {
-                        let mut ident = ident.unwrap_or_else(|| String::new());
+                        let mut ident = ident.unwrap_or_else(String::new);
                         ident.push(c);
                         Some(ident)
                     };
@@ -79,7 +79,7 @@ impl Iterator for TokenIter {
 
     fn next(&mut self) -> Option<Token> {
         loop {
-            let end_byte_at_start = self.end_byte;
+            let end_byte_at_start = self.end_byte();
             match None
                 .or_else(|| self.lex_bf())
                 .or_else(|| self.lex_single_char_token())
@@ -98,11 +98,12 @@ impl Iterator for TokenIter {
                     *self = span;
                 }
             };
-            assert!(self.end_byte > end_byte_at_start);
+            assert!(self.end_byte() > end_byte_at_start);
         }
     }
 }
 
+/// Splits a file into tokens. Anything that is not a token, such as a comment, is skipped.
 pub fn lex(file: Rc<source::File>) -> Vec<Token> {
     Span::at_start_of(file).collect()
 }
@@ -112,7 +113,7 @@ mod tests {
     use super::*;
     use source::span;
 
-    fn ident<'s>(value: &str, span: span::Span) -> Token {
+    fn ident(value: &str, span: span::Span) -> Token {
         Token::Ident(value.to_string(), span)
     }
 
@@ -231,13 +232,13 @@ mod tests {
 
         for i in 0..tokens.len() {
             assert_eq!(
-                tokens[i].span().line_start_byte,
+                tokens[i].span().line_start_byte(),
                 lines[i].0,
                 "Token {} line start byte",
                 i
             );
             assert_eq!(
-                tokens[i].span().line_end_byte,
+                tokens[i].span().line_end_byte(),
                 lines[i].1,
                 "Token {} line end byte",
                 i
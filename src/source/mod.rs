@@ -1,8 +1,12 @@
+//! Loading, lexing and checking Brainfuck source code.
+
+mod brackets;
 mod file;
 mod lexer;
 pub mod span;
 mod token;
 
+pub use self::brackets::check_brackets;
 pub use self::file::File;
 pub use self::lexer::lex;
 pub use self::span::Span;
@@ -5,35 +5,36 @@ use std::rc::Rc;
 use io;
 use source::File;
 
+/// A range of code in a file, with the line and column it starts at
 #[derive(Debug, Clone, PartialEq)]
 pub struct Span {
-    pub src: Rc<File>,
-    pub start_byte: usize,
-    pub end_byte: usize,
-    pub line: u32,
-    pub col: u32,
-    pub width: u32,
-    pub line_start_byte: usize,
-    pub line_end_byte: usize,
+    src: Rc<File>,
+    start_byte: usize,
+    end_byte: usize,
+    line: u32,
+    col: u32,
+    width: u32,
+    line_start_byte: usize,
+    line_end_byte: usize,
 }
 
 fn find_eol_byte(file: &File, start: usize) -> usize {
-    if start >= file.contents.len() {
+    if start >= file.contents().len() {
         return start;
     }
     let offset = start;
-    for (i, c) in file.contents[start..].char_indices() {
+    for (i, c) in file.contents()[start..].char_indices() {
         let i = i + offset;
         if c == '\n' {
             return i;
         }
     }
-    return file.contents.len();
+    file.contents().len()
 }
 
 impl Span {
     pub fn at_start_of(src: Rc<File>) -> Span {
-        let line_end_byte = find_eol_byte(&*src, 0);
+        let line_end_byte = find_eol_byte(&src, 0);
         Span {
             src,
             start_byte: 0,
@@ -46,15 +47,68 @@ impl Span {
         }
     }
 
+    pub fn at_end_of(src: Rc<File>) -> Span {
+        let end = src.contents().len();
+        let mut ret = Span::at_start_of(src);
+        ret.advance_end_to(end);
+        ret.advance_start_to(end);
+        ret
+    }
+
+    /// The file the span is in
+    pub fn src(&self) -> &Rc<File> {
+        &self.src
+    }
+
+    /// Byte offset of the start of the span in the file
+    pub fn start_byte(&self) -> usize {
+        self.start_byte
+    }
+
+    /// Byte offset just past the end of the span in the file
+    pub fn end_byte(&self) -> usize {
+        self.end_byte
+    }
+
+    /// Line the span starts on, counting from 0
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Column the span starts at in chars, counting from 0
+    pub fn col(&self) -> u32 {
+        self.col
+    }
+
+    /// Number of chars in the span
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Byte offset of the start of the line the span starts on
+    pub fn line_start_byte(&self) -> usize {
+        self.line_start_byte
+    }
+
+    /// Byte offset of the end of the line the span starts on, not including the newline
+    pub fn line_end_byte(&self) -> usize {
+        self.line_end_byte
+    }
+
+    /// The code the span covers
+    pub fn text(&self) -> &str {
+        &self.src.contents()[self.start_byte..self.end_byte]
+    }
+
     fn advance_start_to(&mut self, start_byte: usize) {
         assert!(start_byte >= self.start_byte);
         assert!(start_byte <= self.end_byte);
         let offset = self.start_byte;
-        let mut chars = self.src.contents[self.start_byte..].char_indices();
+        let mut chars = self.src.contents()[self.start_byte..].char_indices();
         loop {
             let (i, c) = chars
                 .next()
-                .unwrap_or((self.src.contents.len() - offset, '\0'));
+                .unwrap_or((self.src.contents().len() - offset, '\0'));
             let i = i + offset;
             if i == start_byte {
                 break;
@@ -72,13 +126,14 @@ impl Span {
             self.width -= 1;
             if c == '\n' {
                 self.line += 1;
+                self.col = 0;
                 self.line_start_byte = chars
                     .clone()
                     .next()
-                    .unwrap_or((self.src.contents.len(), '\0'))
+                    .unwrap_or((self.src.contents().len(), '\0'))
                     .0
                     + offset;
-                self.line_end_byte = find_eol_byte(&*self.src, self.line_start_byte);
+                self.line_end_byte = find_eol_byte(&self.src, self.line_start_byte);
             }
         }
         self.start_byte = start_byte;
@@ -86,13 +141,13 @@ impl Span {
 
     fn advance_end_to(&mut self, end_byte: usize) {
         assert!(end_byte >= self.end_byte);
-        assert!(end_byte <= self.src.contents.len());
+        assert!(end_byte <= self.src.contents().len());
         let offset = self.end_byte;
-        let mut chars = self.src.contents[self.end_byte..].char_indices();
+        let mut chars = self.src.contents()[self.end_byte..].char_indices();
         loop {
             let (i, _) = chars
                 .next()
-                .unwrap_or((self.src.contents.len() - offset, '\0'));
+                .unwrap_or((self.src.contents().len() - offset, '\0'));
             let i = i + offset;
             if i == end_byte {
                 break;
@@ -104,11 +159,11 @@ impl Span {
 
     pub fn span_to_byte(&self, end: usize) -> Span {
         assert!(end > self.end_byte);
-        assert!(end <= self.src.contents.len());
+        assert!(end <= self.src.contents().len());
         let mut ret = self.clone();
         ret.advance_end_to(end);
         ret.advance_start_to(self.end_byte);
-        return ret;
+        ret
     }
 
     pub fn between(a: &Span, b: &Span) -> Span {
@@ -119,7 +174,7 @@ impl Span {
             b.clone()
         };
         ret.advance_end_to(cmp::max(a.end_byte, b.end_byte));
-        return ret;
+        ret
     }
 
     pub fn issue(&self, severity: io::Severity, message: &str) -> io::Issue {
@@ -127,6 +182,7 @@ impl Span {
             span: Some(self.clone()),
             severity,
             message: message.to_string(),
+            notes: Vec::new(),
         }
     }
 }
@@ -152,11 +208,14 @@ impl fmt::Debug for Span {
 }
 */
 
+// Makes spans of a file from left to right, for tests to compare against
+#[cfg(test)]
 pub struct Generator {
     src: Rc<File>,
     current: usize,
 }
 
+#[cfg(test)]
 impl Generator {
     pub fn new(src: Rc<File>) -> Generator {
         Generator { src, current: 0 }
@@ -182,6 +241,6 @@ impl Generator {
         let mut ret = Span::at_start_of(self.src.clone());
         ret.advance_end_to(start + bytes);
         ret.advance_start_to(start);
-        return ret;
+        ret
     }
 }
@@ -2,6 +2,7 @@ use runtime;
 use source;
 use std::fmt;
 
+/// A piece of source code with meaning, and where it is
 #[derive(PartialEq, Clone)]
 pub enum Token {
     Linebreak {
@@ -38,7 +39,7 @@ impl fmt::Display for Token {
             }
             Token::Ident(value, _) => write!(f, "${}", value),
             Token::String(value, _) => {
-                write!(f, "\"{}\"", value.chars().map(|c| c).collect::<String>())
+                write!(f, "\"{}\"", value)
             }
             Token::OpenBrace(_) => write!(f, "{{"),
             Token::CloseBrace(_) => write!(f, "}}"),
@@ -55,7 +56,7 @@ impl fmt::Debug for Token {
 }
 
 impl runtime::Op {
-    pub fn token<'s>(self, span: source::Span) -> source::Token {
+    pub fn token(self, span: source::Span) -> source::Token {
         source::Token::Bf(self, span)
     }
 }